@@ -54,9 +54,14 @@
             inherit src;
             strictDeps = true;
 
+            nativeBuildInputs = with pkgs; [
+              pkg-config
+            ];
+
             buildInputs = with pkgs; [
               libGL
               libxkbcommon
+              udev
               vulkan-loader
               wayland
               xorg.libX11
@@ -73,10 +78,10 @@
             pname = "gcviewer";
             cargoExtraArgs = "--no-default-features";
 
-            nativeBuildInputs = with pkgs; [
+            nativeBuildInputs = commonArgs.nativeBuildInputs ++ (with pkgs; [
               copyDesktopItems
               makeWrapper
-            ];
+            ]);
 
             postInstall = ''
               wrapProgram $out/bin/gcviewer \
@@ -2,6 +2,9 @@ use crate::OPENGL_TO_WGPU_MATRIX;
 
 pub struct Camera {
     pub aspect: f32,
+    /// Size of the area that is always visible, in world units.
+    pub width: f32,
+    pub height: f32,
     pub znear: f32,
     pub zfar: f32,
 }
@@ -10,8 +13,8 @@ impl Camera {
     pub fn build_projection_view_matrix(&self) -> cgmath::Matrix4<f32> {
         let view = cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 0.0, -1.0));
 
-        let tw = 2.0f32;
-        let th = 1.0f32;
+        let tw = self.width;
+        let th = self.height;
         let taspect = tw / th;
 
         let proj = if self.aspect > taspect {
@@ -0,0 +1,51 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{layout::Arrangement, source::Source};
+
+/// Settings read from `config.toml`, each named after the command line option overriding it.
+/// Settings that aren't given keep their defaults.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub source: Option<Vec<Source>>,
+    pub host: Option<String>,
+    pub port: Option<Vec<u16>>,
+    pub arrange: Option<Arrangement>,
+    /// Name of the theme drawing the controller or path to its shader, instead of looking for
+    /// `shader.wgsl`.
+    pub theme: Option<String>,
+    /// Name of the layout of the controller or path to it, instead of looking for `layout.toml`.
+    pub layout: Option<String>,
+    pub transparent: Option<bool>,
+    pub borderless: Option<bool>,
+    pub always_on_top: Option<bool>,
+    pub click_through: Option<bool>,
+    pub stats_overlay: Option<bool>,
+    pub stats_interval: Option<f64>,
+    pub trail_length: Option<u16>,
+    pub trail_fade: Option<f32>,
+    pub timeline_duration: Option<f32>,
+    pub game_frames: Option<f64>,
+    /// Width and height of frames rendered in headless mode.
+    pub size: Option<[u32; 2]>,
+    pub fps: Option<f64>,
+}
+
+impl Config {
+    pub fn from_toml(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+
+    /// Makes relative paths relative to `dir`, the directory of the config file, rather than the
+    /// working directory.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        let paths = [(&mut self.layout, ".toml"), (&mut self.theme, ".wgsl")];
+        for (value, extension) in paths {
+            if let Some(path) = value.as_mut().filter(|path| path.ends_with(extension)) {
+                *path = dir.join(&*path).to_string_lossy().into_owned();
+            }
+        }
+    }
+}
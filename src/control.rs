@@ -1,11 +1,18 @@
 use std::mem;
 
+use gcinput::Input;
+use serde::Deserialize;
+
 #[derive(Debug)]
 pub struct Instance {
     pub control: Control,
     pub position: cgmath::Vector2<f32>,
     pub rotation: cgmath::Deg<f32>,
     pub scale: Scale,
+    /// Index of the controller this instance belongs to when showing several.
+    pub player: u32,
+    /// Index of a stick's gate in the shaders' `gates` uniform.
+    pub gate: u32,
 }
 
 impl Instance {
@@ -47,6 +54,8 @@ impl Instance {
             button_pressed: pressed.into(),
             trigger_fill: fill,
             stick_position: position,
+            player: self.player,
+            gate: self.gate,
         }
     }
 }
@@ -61,10 +70,12 @@ pub struct InstanceRaw {
     pub button_pressed: u32,
     pub trigger_fill: f32,
     pub stick_position: [f32; 2],
+    pub player: u32,
+    pub gate: u32,
 }
 
 impl InstanceRaw {
-    const ATTRIBS: [wgpu::VertexAttribute; 10] = wgpu::vertex_attr_array![
+    const ATTRIBS: [wgpu::VertexAttribute; 12] = wgpu::vertex_attr_array![
         5 => Float32x4,
         6 => Float32x4,
         7 => Float32x4,
@@ -75,6 +86,9 @@ impl InstanceRaw {
         12 => Uint32,
         13 => Float32,
         14 => Float32x2,
+        15 => Uint32,
+        // Locations 2 to 4 are unused by the vertices.
+        4 => Uint32,
     ];
 
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -86,13 +100,15 @@ impl InstanceRaw {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(untagged)]
 pub enum Scale {
     Uniform(f32),
     NonUniform(f32, f32),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Button {
     A = 0,
     B = 1,
@@ -106,13 +122,15 @@ pub enum Button {
     Right = 13,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Stick {
     Main = 6,
     C = 7,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Trigger {
     Left = 8,
     Right = 9,
@@ -121,6 +139,8 @@ pub enum Trigger {
 #[derive(Debug, Copy, Clone)]
 pub enum Misc {
     Background = 14,
+    Timeline = 15,
+    FrameCounter = 16,
 }
 
 #[derive(Debug)]
@@ -140,3 +160,32 @@ pub enum Control {
     },
     Misc(Misc),
 }
+
+/// Names of the buttons returned by [`held_buttons`], in bit order.
+pub const HELD_BUTTON_NAMES: [&str; 12] = [
+    "A", "B", "X", "Y", "Z", "L", "R", "Start", "Up", "Down", "Left", "Right",
+];
+
+/// Returns a bit for each button held, in the order of [`HELD_BUTTON_NAMES`]. L and R are the
+/// triggers' full presses.
+pub fn held_buttons(input: &Input) -> u32 {
+    let buttons = [
+        input.button_a,
+        input.button_b,
+        input.button_x,
+        input.button_y,
+        input.button_z,
+        input.button_l,
+        input.button_r,
+        input.button_start,
+        input.button_up,
+        input.button_down,
+        input.button_left,
+        input.button_right,
+    ];
+
+    buttons
+        .iter()
+        .enumerate()
+        .fold(0, |held, (bit, &pressed)| held | u32::from(pressed) << bit)
+}
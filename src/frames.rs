@@ -0,0 +1,150 @@
+use std::time::{Duration, Instant};
+
+use gcinput::Input;
+
+use crate::{
+    control::{held_buttons, HELD_BUTTON_NAMES},
+    source::Sample,
+    state::MAX_PLAYERS,
+};
+
+/// Game frames a button stays highlighted after being held for a single frame.
+const HIGHLIGHT_FRAMES: u32 = 30;
+/// Buttons reported by [`held_buttons`].
+const BUTTONS: usize = HELD_BUTTON_NAMES.len();
+/// Most game frames caught up on at once, e.g. after the window was hidden for a while.
+const MAX_CATCH_UP: u64 = 600;
+
+/// Game frame state as laid out in the shaders' `game_frames` uniform.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GameFrameUniform {
+    /// Game frames since counting started.
+    pub frame: u32,
+    /// Whether inputs are quantised into game frames at all.
+    pub enabled: u32,
+    _padding: [u32; 2],
+    /// Each player's buttons that were last held for a single game frame, in the bit order of
+    /// [`held_buttons`].
+    pub highlights: [u32; MAX_PLAYERS],
+}
+
+/// Quantises the inputs of every controller shown into fixed game frames, as a game polling the
+/// controller once a frame would see them.
+#[derive(Debug)]
+pub struct GameFrames {
+    period: Duration,
+    start: Option<Instant>,
+    /// Game frames completed so far.
+    frame: u64,
+    players: Vec<PlayerFrames>,
+}
+
+#[derive(Debug, Default)]
+struct PlayerFrames {
+    /// Latest input received, which the game sees at the end of the current frame.
+    latest: Input,
+    /// Input of the last completed frame.
+    input: Input,
+    /// Frames each button has been held for.
+    held: [u32; BUTTONS],
+    /// Frames each button stays highlighted for.
+    highlights: [u32; BUTTONS],
+}
+
+impl GameFrames {
+    /// Polls at `rate` frames per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` isn't a finite number greater than 0.
+    pub fn new(rate: f64) -> Self {
+        assert!(
+            rate.is_finite() && rate > 0.0,
+            "game frame rate must be finite and greater than 0, got {rate}"
+        );
+        Self {
+            period: Duration::from_secs_f64(1.0 / rate),
+            start: None,
+            frame: 0,
+            players: Vec::new(),
+        }
+    }
+
+    /// Completes every game frame that ended by `now`, each using the latest of the player's
+    /// `samples` received before the frame ended.
+    pub fn update(&mut self, now: Instant, samples: &[&[Sample]]) {
+        let start = *self.start.get_or_insert(now);
+        let target =
+            (now.saturating_duration_since(start).as_secs_f64() / self.period.as_secs_f64()) as u64;
+        let first = self.frame.max(target.saturating_sub(MAX_CATCH_UP));
+
+        self.players
+            .resize_with(samples.len(), PlayerFrames::default);
+        for (player, samples) in self.players.iter_mut().zip(samples) {
+            let mut samples = samples.iter().peekable();
+            for frame in first..target {
+                let end = start + self.period * (frame + 1) as u32;
+                while let Some(sample) = samples.next_if(|s| s.time < end) {
+                    player.latest = sample.input.clone();
+                }
+                player.complete_frame();
+            }
+
+            // Whatever is left was received during the current frame.
+            if let Some(sample) = samples.last() {
+                player.latest = sample.input.clone();
+            }
+        }
+        self.frame = target;
+    }
+
+    /// Returns the input of `player` in the last completed game frame.
+    pub fn input(&self, player: usize) -> Option<&Input> {
+        self.players.get(player).map(|p| &p.input)
+    }
+
+    pub fn to_uniform(&self) -> GameFrameUniform {
+        let mut highlights = [0; MAX_PLAYERS];
+        for (highlight, player) in highlights.iter_mut().zip(&self.players) {
+            *highlight = player.highlighted_buttons();
+        }
+
+        GameFrameUniform {
+            frame: self.frame as u32,
+            enabled: 1,
+            highlights,
+            ..Default::default()
+        }
+    }
+}
+
+impl PlayerFrames {
+    fn complete_frame(&mut self) {
+        self.input = self.latest.clone();
+
+        let held = held_buttons(&self.input);
+        for (button, (frames, highlight)) in
+            self.held.iter_mut().zip(&mut self.highlights).enumerate()
+        {
+            *highlight = highlight.saturating_sub(1);
+            if held & (1 << button) != 0 {
+                *frames += 1;
+            } else {
+                if *frames == 1 {
+                    *highlight = HIGHLIGHT_FRAMES;
+                }
+                *frames = 0;
+            }
+        }
+    }
+
+    fn highlighted_buttons(&self) -> u32 {
+        self.highlights
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (button, &frames)| {
+                bits | u32::from(frames > 0) << button
+            })
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use gcinput::Input;
+use gilrs::{ev::Code, Axis, Event, EventType, Gamepad, GamepadId, Gilrs};
+use serde::Deserialize;
+
+use crate::{
+    control::{Button, Stick, Trigger},
+    source::{InputSource, Sample, Status},
+};
+
+const DEFAULT_MAPPING: &str = include_str!("gamepad.toml");
+
+/// How far an axis bound to a button has to be pushed for the button to be pressed.
+const AXIS_PRESS_THRESHOLD: f32 = 0.5;
+
+/// Maps gamepad elements to GameCube controller inputs, per kind of gamepad.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+    #[serde(rename = "profile", default)]
+    pub profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Matched case-insensitively against part of the gamepad's name. Matches any gamepad if unset.
+    pub name: Option<String>,
+    #[serde(default)]
+    pub buttons: HashMap<Button, Binding>,
+    #[serde(default)]
+    pub sticks: HashMap<Stick, StickBinding>,
+    #[serde(default)]
+    pub triggers: HashMap<Trigger, TriggerBinding>,
+}
+
+/// A button or axis of a gamepad, either by its gilrs name or its raw event code.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum Binding {
+    Button(gilrs::Button),
+    Axis(Axis),
+    Code(u32),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StickBinding {
+    pub x: Binding,
+    pub y: Binding,
+    #[serde(default)]
+    pub invert_x: bool,
+    #[serde(default)]
+    pub invert_y: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerBinding {
+    pub analog: Binding,
+    pub digital: Option<Binding>,
+}
+
+impl Mapping {
+    pub fn from_toml(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+
+    pub fn profile(&self, gamepad_name: &str) -> Option<&Profile> {
+        let gamepad_name = gamepad_name.to_lowercase();
+        self.profiles.iter().find(|profile| {
+            profile
+                .name
+                .as_ref()
+                .is_none_or(|name| gamepad_name.contains(&name.to_lowercase()))
+        })
+    }
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Self::from_toml(DEFAULT_MAPPING).expect("default gamepad mapping to be valid")
+    }
+}
+
+impl Profile {
+    pub fn read(&self, gamepad: &Gamepad) -> Input {
+        let mut input = neutral_input();
+
+        for (&button, binding) in &self.buttons {
+            *button_mut(&mut input, button) = binding.is_pressed(gamepad);
+        }
+
+        for (&stick, binding) in &self.sticks {
+            let axis = |binding: Binding, invert: bool| {
+                let value = binding.value(gamepad).clamp(-1.0, 1.0);
+                let value = if invert { -value } else { value };
+                ((value + 1.0) / 2.0 * u8::MAX as f32).round() as u8
+            };
+
+            *match stick {
+                Stick::Main => &mut input.main_stick,
+                Stick::C => &mut input.c_stick,
+            } = gcinput::Stick {
+                x: axis(binding.x, binding.invert_x),
+                y: axis(binding.y, binding.invert_y),
+            };
+        }
+
+        for (&trigger, binding) in &self.triggers {
+            let (value, pressed) = match trigger {
+                Trigger::Left => (&mut input.left_trigger, &mut input.button_l),
+                Trigger::Right => (&mut input.right_trigger, &mut input.button_r),
+            };
+
+            *value = (binding.analog.value(gamepad).clamp(0.0, 1.0) * u8::MAX as f32).round() as u8;
+            *pressed = binding
+                .digital
+                .unwrap_or(binding.analog)
+                .is_pressed(gamepad);
+        }
+
+        input
+    }
+}
+
+impl Binding {
+    fn value(self, gamepad: &Gamepad) -> f32 {
+        match self {
+            Self::Button(button) => gamepad.button_data(button).map_or(0.0, |d| d.value()),
+            Self::Axis(axis) => gamepad.value(axis),
+            Self::Code(code) => find_code(gamepad, code).map_or(0.0, |c| gamepad.state().value(c)),
+        }
+    }
+
+    fn is_pressed(self, gamepad: &Gamepad) -> bool {
+        match self {
+            Self::Button(button) => gamepad.is_pressed(button),
+            Self::Axis(axis) => gamepad.value(axis) > AXIS_PRESS_THRESHOLD,
+            Self::Code(code) => find_code(gamepad, code).is_some_and(|c| {
+                let state = gamepad.state();
+                state
+                    .button_data(c)
+                    .map_or_else(|| state.value(c) > AXIS_PRESS_THRESHOLD, |d| d.is_pressed())
+            }),
+        }
+    }
+}
+
+fn find_code(gamepad: &Gamepad, code: u32) -> Option<Code> {
+    let state = gamepad.state();
+    state
+        .buttons()
+        .map(|(c, _)| c)
+        .chain(state.axes().map(|(c, _)| c))
+        .find(|c| c.into_u32() == code)
+}
+
+/// An input with both sticks centered, as opposed to [`Input::default`].
+fn neutral_input() -> Input {
+    let center = gcinput::Stick { x: 128, y: 128 };
+    Input {
+        main_stick: center,
+        c_stick: center,
+        ..Default::default()
+    }
+}
+
+fn button_mut(input: &mut Input, button: Button) -> &mut bool {
+    match button {
+        Button::A => &mut input.button_a,
+        Button::B => &mut input.button_b,
+        Button::X => &mut input.button_x,
+        Button::Y => &mut input.button_y,
+        Button::Start => &mut input.button_start,
+        Button::Z => &mut input.button_z,
+        Button::Up => &mut input.button_up,
+        Button::Down => &mut input.button_down,
+        Button::Left => &mut input.button_left,
+        Button::Right => &mut input.button_right,
+    }
+}
+
+/// Reads local gamepads with gilrs, following whichever gamepad last had a button pressed unless
+/// created [`with_index`](GamepadSource::with_index).
+pub struct GamepadSource {
+    gilrs: Gilrs,
+    mapping: Mapping,
+    index: Option<usize>,
+    active: Option<GamepadId>,
+    previous: Vec<u8>,
+}
+
+impl GamepadSource {
+    pub fn new(mapping: Mapping) -> Result<Self, Box<gilrs::Error>> {
+        Ok(Self {
+            gilrs: Gilrs::new().map_err(Box::new)?,
+            mapping,
+            index: None,
+            active: None,
+            previous: Vec::new(),
+        })
+    }
+
+    /// Follows the `index`th connected gamepad instead, e.g. to show several gamepads at once.
+    pub fn with_index(mut self, index: usize) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    fn log_connected(&self, id: GamepadId) {
+        let gamepad = self.gilrs.gamepad(id);
+        let name = gamepad.name();
+        match self.mapping.profile(name) {
+            Some(profile) => log::info!(
+                "Gamepad connected: {name}, using profile {}",
+                profile.name.as_deref().unwrap_or("default")
+            ),
+            None => log::warn!("Gamepad connected: {name}, no matching profile"),
+        }
+    }
+}
+
+impl InputSource for GamepadSource {
+    fn poll(&mut self, _elapsed: Duration, samples: &mut Vec<Sample>) {
+        while let Some(Event { id, event, .. }) = self.gilrs.next_event() {
+            match event {
+                EventType::Connected => self.log_connected(id),
+                EventType::Disconnected => {
+                    log::info!("Gamepad disconnected: {}", self.gilrs.gamepad(id).name());
+                    if self.active == Some(id) {
+                        self.active = None;
+                    }
+                }
+                EventType::ButtonPressed(..) if self.index.is_none() => self.active = Some(id),
+                EventType::ButtonChanged(gilrs::Button::Unknown, value, code)
+                | EventType::AxisChanged(Axis::Unknown, value, code) => {
+                    log::debug!("Unmapped element with code {} = {value}", code.into_u32());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(index) = self.index {
+            self.active = self.gilrs.gamepads().nth(index).map(|(id, _)| id);
+        } else if self.active.is_none() {
+            // Until a button is pressed, follow the first connected gamepad.
+            self.active = self.gilrs.gamepads().next().map(|(id, _)| id);
+        }
+
+        let input = self
+            .active
+            .map(|id| self.gilrs.gamepad(id))
+            .and_then(|gamepad| {
+                self.mapping
+                    .profile(gamepad.name())
+                    .map(|profile| profile.read(&gamepad))
+            })
+            .unwrap_or_else(neutral_input);
+
+        let data = bincode::serialize(&input).unwrap();
+        if data != self.previous {
+            samples.push(Sample {
+                time: Instant::now(),
+                input,
+            });
+            self.previous = data;
+        }
+    }
+
+    fn status(&self) -> Status {
+        if self.active.is_some() {
+            Status::Connected
+        } else {
+            Status::Disconnected
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{de::Error, Deserialize, Deserializer};
+
+use crate::layout::STICK_CENTER;
+
+/// Most sticks of a layout with their own gate, the length of the shaders' `gates` uniform. Later
+/// sticks draw the default gate without markers.
+pub const MAX_GATES: usize = 8;
+/// Most notches of a custom gate.
+pub const MAX_NOTCHES: usize = 16;
+
+/// Distance the center of a stick moves over its whole raw range, relative to its size.
+pub const STICK_RANGE: f32 = 0.6;
+
+/// Distance of a circular gate from the center, in raw stick units, about as far as GameCube
+/// controller sticks reach.
+const CIRCLE_GATE_RADIUS: f32 = 100.0;
+/// Raw stick units per unit of Melee's stick coordinates.
+const MELEE_UNIT: f32 = 80.0;
+
+/// Gate shape, deadzone and thresholds drawn for a stick.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gate {
+    #[serde(default)]
+    pub gate: GateShape,
+    /// Raw positions of each notch in order around the gate, as shown by stick readouts. Only used
+    /// by [`GateShape::Notches`].
+    #[serde(default, deserialize_with = "deserialize_notches")]
+    pub notches: Vec<[u8; 2]>,
+    /// Radius of the deadzone in the normalised units of stick readouts, 0 to draw none.
+    #[serde(default)]
+    pub deadzone: f32,
+    pub thresholds: Option<Thresholds>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GateShape {
+    #[default]
+    Octagon = 0,
+    Circle = 1,
+    /// Polygon through the positions given by [`Gate::notches`].
+    Notches = 2,
+}
+
+/// Coordinate thresholds of a game marked around a stick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Thresholds {
+    /// Melee's deadzone, dash and smash, tap jump and fast fall thresholds and the rim its
+    /// coordinates are clamped to.
+    Melee,
+}
+
+/// A stick's gate as laid out in the shaders' `gates` uniform, with every distance in the stick's
+/// local space.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GateUniform {
+    shape: u32,
+    notch_count: u32,
+    radius: f32,
+    deadzone: f32,
+    melee_unit: f32,
+    _padding: [f32; 3],
+    notches: [[f32; 4]; MAX_NOTCHES],
+}
+
+impl Gate {
+    pub fn to_uniform(&self) -> GateUniform {
+        let mut notches = [[0.0; 4]; MAX_NOTCHES];
+        for (notch, [x, y]) in notches.iter_mut().zip(&self.notches) {
+            let offset = |n: u8| to_local(n as f32 - STICK_CENTER as f32);
+            *notch = [offset(*x), offset(*y), 0.0, 0.0];
+        }
+
+        GateUniform {
+            shape: self.gate as u32,
+            notch_count: self.notches.len().min(MAX_NOTCHES) as u32,
+            radius: to_local(CIRCLE_GATE_RADIUS),
+            deadzone: to_local(self.deadzone * (u8::MAX - STICK_CENTER) as f32),
+            melee_unit: match self.thresholds {
+                Some(Thresholds::Melee) => to_local(MELEE_UNIT),
+                None => 0.0,
+            },
+            notches,
+            ..Default::default()
+        }
+    }
+}
+
+fn deserialize_notches<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<[u8; 2]>, D::Error> {
+    let notches = Vec::deserialize(deserializer)?;
+    if notches.len() > MAX_NOTCHES {
+        return Err(D::Error::custom(format!(
+            "a gate has at most {MAX_NOTCHES} notches, got {}",
+            notches.len()
+        )));
+    }
+    Ok(notches)
+}
+
+/// Converts a distance in raw stick units to the stick's local space.
+fn to_local(raw: f32) -> f32 {
+    raw / u8::MAX as f32 * STICK_RANGE
+}
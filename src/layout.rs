@@ -0,0 +1,380 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use gcinput::Input;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    control::{Button, Control, Instance, Misc, Scale, Stick, Trigger},
+    gate::{Gate, GateUniform, MAX_GATES, STICK_RANGE},
+    named::{self, Named},
+    text::text_size,
+};
+
+const DEFAULT_LAYOUT: &str = include_str!("layout.toml");
+pub const DEFAULT_LAYOUT_NAME: &str = "default";
+
+/// Raw value of a stick axis at rest.
+pub const STICK_CENTER: u8 = 128;
+
+/// Size of the area a layout is drawn in, in world units.
+pub const LAYOUT_SIZE: cgmath::Vector2<f32> = cgmath::vec2(2.0, 1.0);
+
+/// How several controllers are placed next to each other.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arrangement {
+    /// Side by side.
+    #[default]
+    Row,
+    /// Stacked on top of each other.
+    Column,
+    /// In a grid that is as close to square as possible.
+    Grid,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    #[serde(rename = "element", default)]
+    pub elements: Vec<Element>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Element {
+    #[serde(flatten)]
+    pub kind: ElementKind,
+    pub position: [f32; 2],
+    #[serde(default)]
+    pub rotation: f32,
+    pub scale: Scale,
+}
+
+/// A text or readout element of a [`Layout`].
+#[derive(Debug, Clone)]
+pub struct Label<'l> {
+    pub text: Cow<'l, str>,
+    pub color: [f32; 4],
+    pub position: cgmath::Vector2<f32>,
+    pub rotation: cgmath::Deg<f32>,
+    pub scale: Scale,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ElementKind {
+    Background,
+    /// Scrolling history of the buttons and triggers.
+    Timeline,
+    /// Game frame counter, only shown while inputs are quantised into game frames.
+    FrameCounter,
+    /// Text label, e.g. the name of a button.
+    Text {
+        text: String,
+        #[serde(default = "default_text_color")]
+        color: [f32; 4],
+    },
+    /// Raw and normalised position, angle and magnitude of a stick.
+    StickReadout {
+        stick: Stick,
+        #[serde(default = "default_text_color")]
+        color: [f32; 4],
+    },
+    /// Raw and normalised analog value of a trigger.
+    TriggerReadout {
+        trigger: Trigger,
+        #[serde(default = "default_text_color")]
+        color: [f32; 4],
+    },
+    Button {
+        button: Button,
+        input: Option<Button>,
+    },
+    Stick {
+        stick: Stick,
+        input: Option<Stick>,
+        #[serde(flatten)]
+        gate: Gate,
+    },
+    Trigger {
+        trigger: Trigger,
+        input: Option<Trigger>,
+    },
+}
+
+impl Layout {
+    pub fn from_toml(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+
+    pub fn instances(&self, input: &Input) -> Vec<Instance> {
+        let mut sticks = 0;
+        self.elements
+            .iter()
+            .filter_map(|element| {
+                let mut instance = element.to_instance(input)?;
+                if let ElementKind::Stick { .. } = element.kind {
+                    instance.gate = sticks.min(MAX_GATES) as u32;
+                    sticks += 1;
+                }
+                Some(instance)
+            })
+            .collect()
+    }
+
+    /// Returns the gates of the first [`MAX_GATES`] sticks, indexed by [`Instance::gate`].
+    pub fn gates(&self) -> [GateUniform; MAX_GATES] {
+        let mut gates = [GateUniform::default(); MAX_GATES];
+        let sticks = self
+            .elements
+            .iter()
+            .filter_map(|element| match &element.kind {
+                ElementKind::Stick { gate, .. } => Some(gate),
+                _ => None,
+            });
+        for (uniform, gate) in gates.iter_mut().zip(sticks) {
+            *uniform = gate.to_uniform();
+        }
+        gates
+    }
+
+    /// Returns the text labels and readouts of `input`, which are drawn on top of the controls
+    /// rather than as instances.
+    pub fn labels<'l>(&'l self, input: &'l Input) -> impl Iterator<Item = Label<'l>> {
+        self.elements.iter().filter_map(move |element| {
+            let (text, color) = match &element.kind {
+                ElementKind::Text { text, color } => (Cow::Borrowed(text.as_str()), color),
+                ElementKind::StickReadout { stick, color } => (
+                    Cow::Owned(stick_readout(match stick {
+                        Stick::Main => &input.main_stick,
+                        Stick::C => &input.c_stick,
+                    })),
+                    color,
+                ),
+                ElementKind::TriggerReadout { trigger, color } => {
+                    (Cow::Owned(trigger_readout(*trigger, input)), color)
+                }
+                _ => return None,
+            };
+
+            Some(Label {
+                text,
+                color: *color,
+                position: element.position.into(),
+                rotation: cgmath::Deg(element.rotation),
+                scale: element.scale,
+            })
+        })
+    }
+}
+
+impl Arrangement {
+    /// Returns the number of columns and rows needed to place `count` controllers.
+    pub fn grid_size(self, count: usize) -> (usize, usize) {
+        let count = count.max(1);
+        match self {
+            Self::Row => (count, 1),
+            Self::Column => (1, count),
+            Self::Grid => {
+                let columns = count.isqrt() + usize::from(count.isqrt().pow(2) < count);
+                (columns, count.div_ceil(columns))
+            }
+        }
+    }
+
+    /// Returns the offset of the center of the `index`th of `count` controllers from the center of
+    /// the whole arrangement, filling rows top to bottom.
+    pub fn offset(self, index: usize, count: usize) -> cgmath::Vector2<f32> {
+        let (columns, rows) = self.grid_size(count);
+        let (column, row) = (index % columns, index / columns);
+        cgmath::vec2(
+            (column as f32 - (columns - 1) as f32 / 2.0) * LAYOUT_SIZE.x,
+            ((rows - 1) as f32 / 2.0 - row as f32) * LAYOUT_SIZE.y,
+        )
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::from_toml(DEFAULT_LAYOUT).expect("default layout to be valid")
+    }
+}
+
+/// A layout that can be selected by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NamedLayout {
+    Default,
+    /// A layout file, named after the file without its extension.
+    File {
+        name: String,
+        path: PathBuf,
+    },
+}
+
+impl Named for NamedLayout {
+    const KIND: &'static str = "layout";
+    const EXTENSION: &'static str = "toml";
+
+    fn file(name: String, path: PathBuf) -> Self {
+        Self::File { name, path }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Default => DEFAULT_LAYOUT_NAME,
+            Self::File { name, .. } => name,
+        }
+    }
+}
+
+impl NamedLayout {
+    /// Returns the path of the layout file, or `None` for the default layout.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Default => None,
+            Self::File { path, .. } => Some(path),
+        }
+    }
+}
+
+/// Returns the default layout followed by the `.toml` files in `dir` sorted by name. A file named
+/// `default.toml` replaces the default layout.
+pub fn discover_layouts(dir: Option<&Path>) -> Vec<NamedLayout> {
+    named::discover(vec![NamedLayout::Default], dir)
+}
+
+impl Element {
+    /// Returns the control drawn for this element, or `None` for text labels and readouts.
+    pub fn to_instance(&self, input: &Input) -> Option<Instance> {
+        let control = match self.kind {
+            ElementKind::Text { .. }
+            | ElementKind::StickReadout { .. }
+            | ElementKind::TriggerReadout { .. } => return None,
+            ElementKind::Background => Control::Misc(Misc::Background),
+            ElementKind::Timeline => Control::Misc(Misc::Timeline),
+            ElementKind::FrameCounter => Control::Misc(Misc::FrameCounter),
+            ElementKind::Button {
+                button,
+                input: binding,
+            } => Control::Button {
+                button,
+                pressed: button_pressed(input, binding.unwrap_or(button)),
+            },
+            ElementKind::Stick {
+                stick,
+                input: binding,
+                ..
+            } => Control::Stick {
+                stick,
+                position: stick_to_vec2(match binding.unwrap_or(stick) {
+                    Stick::Main => &input.main_stick,
+                    Stick::C => &input.c_stick,
+                }),
+            },
+            ElementKind::Trigger {
+                trigger,
+                input: binding,
+            } => {
+                let (value, pressed) = match binding.unwrap_or(trigger) {
+                    Trigger::Left => (input.left_trigger, input.button_l),
+                    Trigger::Right => (input.right_trigger, input.button_r),
+                };
+
+                Control::Trigger {
+                    trigger,
+                    fill: value as f32 / u8::MAX as f32,
+                    pressed,
+                }
+            }
+        };
+
+        Some(Instance {
+            control,
+            position: self.position.into(),
+            rotation: cgmath::Deg(self.rotation),
+            scale: self.scale,
+            player: 0,
+            gate: 0,
+        })
+    }
+}
+
+impl Label<'_> {
+    /// Returns the matrix mapping the units of [`TextBuffer::push`](crate::text::TextBuffer::push)
+    /// to world space, centering the label on its position. The scale is the height of a line.
+    pub fn model_matrix(&self) -> cgmath::Matrix4<f32> {
+        let (scale_x, scale_y) = match self.scale {
+            Scale::Uniform(s) => (s, s),
+            Scale::NonUniform(x, y) => (x, y),
+        };
+        let size = text_size(&self.text);
+
+        cgmath::Matrix4::from_translation(self.position.extend(0.0))
+            * cgmath::Matrix4::from_angle_z(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(scale_x, scale_y, 1.0)
+            * cgmath::Matrix4::from_translation(cgmath::vec3(-size.x / 2.0, size.y / 2.0, 0.0))
+    }
+}
+
+fn default_text_color() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+/// Maps a raw stick axis or trigger value centered on `center` to [-1, 1], or [0, 1] for
+/// triggers.
+fn normalise(value: u8, center: u8) -> f32 {
+    let range = (u8::MAX - center).max(1) as f32;
+    ((value as f32 - center as f32) / range).clamp(-1.0, 1.0)
+}
+
+/// Formats the raw and normalised position of `stick` followed by its angle in degrees
+/// counterclockwise from the right and its magnitude. Every line keeps the same width so centered
+/// readouts don't shift as the values change.
+fn stick_readout(stick: &gcinput::Stick) -> String {
+    let (x, y) = (
+        normalise(stick.x, STICK_CENTER),
+        normalise(stick.y, STICK_CENTER),
+    );
+    let magnitude = x.hypot(y);
+    let angle = if magnitude > 0.0 {
+        format!("{:5.1}", y.atan2(x).to_degrees().rem_euclid(360.0))
+    } else {
+        "    -".to_owned()
+    };
+
+    format!(
+        "X {:3} {x:+.3}\nY {:3} {y:+.3}\n{angle} deg {magnitude:.3}",
+        stick.x, stick.y
+    )
+}
+
+/// Formats the raw and normalised analog value of `trigger`.
+fn trigger_readout(trigger: Trigger, input: &Input) -> String {
+    let (name, value) = match trigger {
+        Trigger::Left => ("L", input.left_trigger),
+        Trigger::Right => ("R", input.right_trigger),
+    };
+    format!("{name} {value:3} {:.3}", normalise(value, 0))
+}
+
+fn button_pressed(input: &Input, button: Button) -> bool {
+    match button {
+        Button::A => input.button_a,
+        Button::B => input.button_b,
+        Button::X => input.button_x,
+        Button::Y => input.button_y,
+        Button::Start => input.button_start,
+        Button::Z => input.button_z,
+        Button::Up => input.button_up,
+        Button::Down => input.button_down,
+        Button::Left => input.button_left,
+        Button::Right => input.button_right,
+    }
+}
+
+fn stick_to_vec2(stick: &gcinput::Stick) -> cgmath::Vector2<f32> {
+    let f = |n: u8| ((u8::MAX - n) as f32 / u8::MAX as f32 - 0.5) * STICK_RANGE;
+    cgmath::vec2(f(stick.x), f(stick.y))
+}
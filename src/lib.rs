@@ -1,8 +1,27 @@
 use std::mem;
 
 pub mod camera;
+pub mod config;
 pub mod control;
+pub mod frames;
+pub mod gamepad;
+pub mod gate;
+pub mod layout;
+pub mod named;
+pub mod overlay;
+pub mod record;
+pub mod replay;
+pub mod session;
+pub mod shader;
+pub mod source;
 pub mod state;
+pub mod stats;
+pub mod text;
+pub mod theme;
+pub mod timeline;
+pub mod trail;
+pub mod udp;
+pub mod watcher;
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
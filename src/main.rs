@@ -1,107 +1,921 @@
 use std::{
-    env, fs,
-    io::Read,
-    mem,
-    net::UdpSocket,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
-    },
-    thread::{self, JoinHandle},
-    time::Duration,
+    env, fs, mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
-use clap::Parser;
-use enclose::enclose;
+use clap::{
+    error::ErrorKind, parser::ValueSource, ArgAction, ArgMatches, CommandFactory, FromArgMatches,
+    Parser,
+};
 use gcinput::Input;
-use gcviewer::state::State;
+use gcviewer::{
+    config::Config,
+    gamepad::{self, GamepadSource},
+    layout::{self, Arrangement, Layout, NamedLayout},
+    named::Named,
+    record::Recorder,
+    replay::Replay,
+    session::{Session, WindowGeometry},
+    shader,
+    source::{self, InputSource, Sample, Source, Status},
+    state::{Player, State, DEFAULT_TIMELINE_DURATION, MAX_PLAYERS},
+    stats::{self, LatencyStats, PlayerStats, PressReport, PressStats},
+    theme::{self, Theme},
+    trail::MAX_TRAIL_LENGTH,
+    udp::UdpSource,
+    watcher::FileWatcher,
+};
 use winit::{
     application::ApplicationHandler,
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
     event::*,
     event_loop::EventLoop,
-    window::{Icon, Window, WindowAttributes},
+    keyboard::{Key, ModifiersState, NamedKey},
+    window::{Icon, Window, WindowAttributes, WindowLevel},
 };
 
 const ICON_FILE: &[u8] = include_bytes!("../resource/icon.png");
 
 fn main() {
-    let exe_path = env::current_exe().expect("Failed to get current exe path");
-    env::set_current_dir(
-        exe_path
-            .parent()
-            .expect("Failed to get current exe parent path"),
-    )
-    .expect("Failed to set current working directory");
-
     env_logger::init();
 
-    let args = Args::parse();
-    pollster::block_on(run(&args, load_custom_shader()));
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let themes = theme::discover(themes_dir().as_deref());
+    if args.list_themes {
+        for theme in &themes {
+            match theme.path() {
+                Some(path) => println!("{}\t{}", theme.name(), path.display()),
+                None => println!("{}", theme.name()),
+            }
+        }
+        return;
+    }
+
+    let config = load_config(args.config.as_deref());
+
+    // Headless runs are usually scripted, so they neither restore nor save the session.
+    let mut session = match (&args.headless, session_path()) {
+        (None, Some(path)) => Session::load(&path),
+        _ => Session::default(),
+    };
+    // The command line takes precedence over the session, so that choices made at runtime aren't
+    // lost to config.toml, which in turn takes precedence over the defaults. A file given with
+    // --config is a profile picked for this run though, so it takes precedence over the session.
+    if args.config.is_some() {
+        session.forget(&config);
+    }
+    args.apply_config(&matches, &config);
+    args.apply_session(&matches, &session);
+    let session = args.to_session(&matches, &session);
+
+    let theme = select(args.theme.as_deref(), &themes, "shader.wgsl");
+    let layouts = layout::discover_layouts(layouts_dir().as_deref());
+    let layout = select(args.layout.as_deref(), &layouts, "layout.toml");
+    pollster::block_on(run(
+        &args,
+        Selection::new(themes, theme, session.theme.clone()),
+        Selection::new(layouts, layout, session.layout.clone()),
+        session,
+    ));
+}
+
+/// Reads the config file given by `--config`, exiting if it can't be read, or else `config.toml`
+/// if there is one.
+fn load_config(path: Option<&Path>) -> Config {
+    let Some(path) = path
+        .map(Path::to_path_buf)
+        .or_else(|| find_config_file("config.toml"))
+    else {
+        return Config::default();
+    };
+
+    let s = fs::read_to_string(&path).unwrap_or_else(|e| {
+        exit_with_error(
+            ErrorKind::Io,
+            format!("failed to read config {}: {e}", path.display()),
+        )
+    });
+    let mut config = Config::from_toml(&s).unwrap_or_else(|e| {
+        exit_with_error(
+            ErrorKind::InvalidValue,
+            format!("failed to parse config {}: {e}", path.display()),
+        )
+    });
+    if let Some(dir) = path.parent() {
+        config.resolve_paths(dir);
+    }
+
+    config
+}
+
+/// Finds the theme or layout given by `selector`, a name or a path to a file, or else `file_name`
+/// if there is one. Falls back to the first of `choices`, the default.
+fn select<T: Named + Clone>(selector: Option<&str>, choices: &[T], file_name: &str) -> T {
+    let default = || choices[0].clone();
+    match selector {
+        Some(path) if path.ends_with(&format!(".{}", T::EXTENSION)) => T::from_path(path),
+        Some(name) => choices
+            .iter()
+            .find(|choice| choice.name() == name)
+            .cloned()
+            .unwrap_or_else(|| {
+                let names = choices.iter().map(T::name).collect::<Vec<_>>();
+                log::error!(
+                    "Unknown {} `{name}`, using default. Available {}s: {}",
+                    T::KIND,
+                    T::KIND,
+                    names.join(", ")
+                );
+                default()
+            }),
+        None => find_config_file(file_name).map_or_else(default, T::from_path),
+    }
 }
 
-fn load_custom_shader() -> Option<String> {
-    fs::File::open("shader.wgsl")
+/// Returns the path of the theme's shader to watch for changes, if any, and its source unless it
+/// is the default shader or invalid.
+fn load_theme(theme: &Theme) -> (Option<PathBuf>, Option<String>) {
+    let source = read_theme(theme)
+        .inspect_err(|e| log::error!("Failed to load theme {}, using default: {e}", theme.name()))
         .ok()
-        .or_else(|| {
-            directories::BaseDirs::new()
-                .map(|dirs| dirs.config_dir().join("gcviewer").join("shader.wgsl"))
-                .and_then(|path| fs::File::open(path).ok())
+        .flatten();
+
+    // Still watch an invalid shader so fixing it is picked up without a restart.
+    (theme.path().map(Path::to_path_buf), source)
+}
+
+/// Reads and validates the theme's shader, returning `None` for the default shader.
+fn read_theme(theme: &Theme) -> Result<Option<String>, String> {
+    if theme.is_default() {
+        return Ok(None);
+    }
+
+    let path = theme.path().unwrap_or(Path::new(theme.name()));
+    let source = theme
+        .load()
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    shader::validate(&source, path).map_err(|e| format!("invalid shader:\n{e}"))?;
+
+    Ok(Some(source.into_owned()))
+}
+
+fn load_layout(layout: &NamedLayout) -> Layout {
+    read_layout(layout)
+        .inspect_err(|e| {
+            log::error!(
+                "Failed to load layout {}, using default: {e}",
+                layout.name()
+            )
         })
-        .and_then(|mut f| {
-            let mut s = String::new();
-            f.read_to_string(&mut s).map(|_| s).ok()
+        .unwrap_or_default()
+}
+
+fn read_layout(layout: &NamedLayout) -> Result<Layout, String> {
+    let Some(path) = layout.path() else {
+        return Ok(Layout::default());
+    };
+    let s =
+        fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    Layout::from_toml(&s).map_err(|e| format!("failed to parse {}: {e}", path.display()))
+}
+
+fn load_gamepad_mapping() -> Option<gamepad::Mapping> {
+    find_config_file("gamepad.toml")
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|s| {
+            gamepad::Mapping::from_toml(&s)
+                .inspect_err(|e| log::error!("Failed to parse gamepad.toml, using default: {e}"))
+                .ok()
         })
 }
 
+fn find_config_file(name: &str) -> Option<PathBuf> {
+    [exe_dir(), config_dir()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Returns the directory of the executable, where config files take precedence over the config
+/// directory. Relative paths given as arguments are relative to the working directory instead.
+fn exe_dir() -> Option<PathBuf> {
+    env::current_exe()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+}
+
+fn config_dir() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|dirs| dirs.config_dir().join("gcviewer"))
+}
+
+fn themes_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("themes"))
+}
+
+fn layouts_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("layouts"))
+}
+
+fn session_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("session.toml"))
+}
+
 #[derive(Parser)]
 struct Args {
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Reads settings from the specified file instead of config.toml. Options given on the command line take precedence."
+    )]
+    config: Option<PathBuf>,
+
     #[arg(
         short,
         long,
-        default_value_t = 4096,
-        help = "Connects to UDP server on the specified port."
+        default_value = "4096",
+        help = "Connects to UDP server on the specified port. Repeat to show a controller for each port."
+    )]
+    port: Vec<u16>,
+
+    #[arg(
+        long,
+        default_value = "127.0.0.1",
+        value_parser = parse_host,
+        help = "Connects to UDP server on the specified host, an IPv4 or IPv6 address or a hostname."
+    )]
+    host: String,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "udp",
+        help = "Where to read inputs from. Repeat to show a controller for each source, with each udp source using the next --port."
+    )]
+    source: Vec<Source>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Arrangement::Row,
+        help = "How to place controllers when showing several."
+    )]
+    arrange: Arrangement,
+
+    #[arg(
+        long,
+        value_name = "NAME|FILE",
+        help = "Draws the controller with the specified theme, by name as listed by --list-themes or as a path to a .wgsl file, instead of shader.wgsl. F5 and Shift+F5 switch themes while running."
+    )]
+    theme: Option<String>,
+
+    #[arg(long, help = "Lists the built-in themes and user themes, then exits.")]
+    list_themes: bool,
+
+    #[arg(
+        long,
+        value_name = "NAME|FILE",
+        help = "Places controls according to the specified layout, by name of a file in the layouts config directory or as a path to a .toml file, instead of layout.toml. F6 and Shift+F6 switch layouts while running."
+    )]
+    layout: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Renders without a window, writing PNG frames to the specified directory."
+    )]
+    headless: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "replay",
+        help = "Records every received input with a timestamp to the specified file."
+    )]
+    record: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "source",
+        help = "Replays inputs from a file written by --record instead of reading from --source. Space pauses and . steps one frame while paused."
+    )]
+    replay: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        value_parser = parse_positive,
+        help = "Playback speed multiplier for --replay."
     )]
-    port: u16,
+    replay_speed: f64,
+
+    #[arg(long, help = "Loops playback of --replay.")]
+    replay_loop: bool,
+
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        default_value = "512x256",
+        value_parser = parse_size,
+        help = "Size of frames rendered in headless mode."
+    )]
+    size: PhysicalSize<u32>,
+
+    #[arg(
+        long,
+        default_value_t = 60.0,
+        value_parser = parse_positive,
+        help = "Frames per second rendered in headless mode."
+    )]
+    fps: f64,
+
+    #[arg(
+        long,
+        help = "Number of frames to render in headless mode before exiting. Renders until killed if not specified."
+    )]
+    frames: Option<u64>,
+
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = ArgAction::Set,
+        default_value_t = false,
+        help = "Shows bars for each controller's input latency, packet jitter and packet loss, and their press counts and actions per minute. F3 toggles them, F4 resets the press counts."
+    )]
+    stats_overlay: bool,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_positive,
+        help = "Logs each controller's input latency and packet statistics at the specified interval."
+    )]
+    stats_interval: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u16).range(0..=MAX_TRAIL_LENGTH as i64),
+        help = "Draws a trail behind each stick of its positions over the specified number of frames, up to 64."
+    )]
+    trail_length: u16,
+
+    #[arg(
+        long,
+        default_value_t = 0.9,
+        value_parser = parse_fraction,
+        help = "Opacity kept by each frame older point of the stick trails, from 0 to 1."
+    )]
+    trail_fade: f32,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = DEFAULT_TIMELINE_DURATION,
+        value_parser = |s: &str| parse_positive(s).map(|seconds| seconds as f32),
+        help = "Seconds of input history shown by timeline elements of the layout, up to 10."
+    )]
+    timeline_duration: f32,
+
+    #[arg(
+        long,
+        value_name = "RATE",
+        num_args = 0..=1,
+        default_missing_value = "60",
+        value_parser = parse_positive,
+        help = "Shows inputs as a game polling at the specified frames per second would, with a frame counter and highlighting buttons held for a single frame. Defaults to 60 when no rate is given."
+    )]
+    game_frames: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Writes each controller's press counts and actions per minute to the specified file on exit, as JSON if it ends in .json and CSV otherwise. F4 resets the counts."
+    )]
+    press_stats: Option<PathBuf>,
+
+    #[command(flatten)]
+    window: WindowArgs,
 }
 
-struct SocketContext {
-    socket: UdpSocket,
-    input: Arc<Mutex<Input>>,
-    stop_flag: AtomicBool,
+impl Args {
+    /// Restores the settings of the last session that weren't given on the command line.
+    fn apply_session(&mut self, matches: &ArgMatches, session: &Session) {
+        let given = |id| matches.value_source(id) == Some(ValueSource::CommandLine);
+        // The host and ports only make sense with the sources they were used with.
+        if !given("source") {
+            merge(&mut self.source, false, session.source.clone());
+            merge(&mut self.host, given("host"), session.host.clone());
+            merge(&mut self.port, given("port"), session.port.clone());
+        }
+        merge(&mut self.arrange, given("arrange"), session.arrange);
+        merge(
+            &mut self.theme,
+            given("theme"),
+            session.theme.clone().map(Some),
+        );
+        merge(
+            &mut self.layout,
+            given("layout"),
+            session.layout.clone().map(Some),
+        );
+    }
+
+    /// Applies the settings of `config` that weren't given on the command line.
+    fn apply_config(&mut self, matches: &ArgMatches, config: &Config) {
+        let given = |id| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        let host = config
+            .host
+            .as_deref()
+            .map(parse_host)
+            .map(|host| host.unwrap_or_else(|e| invalid_config(e)));
+        let size = config.size.map(|[width, height]| {
+            parse_size(&format!("{width}x{height}")).unwrap_or_else(|e| invalid_config(e))
+        });
+        if config
+            .trail_length
+            .is_some_and(|length| length as usize > MAX_TRAIL_LENGTH)
+        {
+            invalid_config(format!("trail_length must be at most {MAX_TRAIL_LENGTH}"));
+        }
+        if config.source.as_ref().is_some_and(Vec::is_empty) {
+            invalid_config("source must list at least one source".to_owned());
+        }
+        if config.port.as_ref().is_some_and(Vec::is_empty) {
+            invalid_config("port must list at least one port".to_owned());
+        }
+        let positive = [
+            ("fps", config.fps),
+            ("game_frames", config.game_frames),
+            ("stats_interval", config.stats_interval),
+            ("timeline_duration", config.timeline_duration.map(f64::from)),
+        ];
+        for (key, value) in positive {
+            if let Some(Err(e)) = value.map(check_positive) {
+                invalid_config(format!("{key} {e}"));
+            }
+        }
+        if let Some(Err(e)) = config.trail_fade.map(check_fraction) {
+            invalid_config(format!("trail_fade {e}"));
+        }
+
+        merge(&mut self.source, given("source"), config.source.clone());
+        merge(&mut self.host, given("host"), host);
+        merge(&mut self.port, given("port"), config.port.clone());
+        merge(&mut self.arrange, given("arrange"), config.arrange);
+        merge(
+            &mut self.theme,
+            given("theme"),
+            config.theme.clone().map(Some),
+        );
+        merge(
+            &mut self.layout,
+            given("layout"),
+            config.layout.clone().map(Some),
+        );
+
+        let window = &mut self.window;
+        merge(
+            &mut window.transparent,
+            given("transparent"),
+            config.transparent,
+        );
+        merge(
+            &mut window.borderless,
+            given("borderless"),
+            config.borderless,
+        );
+        merge(
+            &mut window.always_on_top,
+            given("always_on_top"),
+            config.always_on_top,
+        );
+        merge(
+            &mut window.click_through,
+            given("click_through"),
+            config.click_through,
+        );
+
+        merge(
+            &mut self.stats_overlay,
+            given("stats_overlay"),
+            config.stats_overlay,
+        );
+        merge(
+            &mut self.stats_interval,
+            given("stats_interval"),
+            config.stats_interval.map(Some),
+        );
+        merge(
+            &mut self.trail_length,
+            given("trail_length"),
+            config.trail_length,
+        );
+        merge(&mut self.trail_fade, given("trail_fade"), config.trail_fade);
+        merge(
+            &mut self.timeline_duration,
+            given("timeline_duration"),
+            config.timeline_duration,
+        );
+        merge(
+            &mut self.game_frames,
+            given("game_frames"),
+            config.game_frames.map(Some),
+        );
+        merge(&mut self.size, given("size"), size);
+        merge(&mut self.fps, given("fps"), config.fps);
+    }
+
+    /// Returns the settings to restore next time: those given on the command line or restored
+    /// from `session`. Settings from the config file are left out so that changing it takes effect.
+    fn to_session(&self, matches: &ArgMatches, session: &Session) -> Session {
+        let given = |id| matches.value_source(id) == Some(ValueSource::CommandLine);
+        let kept = |id, restored: bool| given(id) || restored;
+        // The host and ports are kept together with the sources they were used with.
+        let inputs = kept("source", session.source.is_some()) || given("host") || given("port");
+
+        Session {
+            source: inputs.then(|| self.source.clone()),
+            host: inputs.then(|| self.host.clone()),
+            port: inputs.then(|| self.port.clone()),
+            arrange: kept("arrange", session.arrange.is_some()).then_some(self.arrange),
+            theme: self
+                .theme
+                .clone()
+                .filter(|_| kept("theme", session.theme.is_some())),
+            layout: self
+                .layout
+                .clone()
+                .filter(|_| kept("layout", session.layout.is_some())),
+            window: session.window,
+        }
+    }
+}
+
+// How the window is shown, e.g. for window capture in OBS.
+#[derive(Clone, Copy, clap::Args)]
+struct WindowArgs {
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = ArgAction::Set,
+        default_value_t = false,
+        help = "Draws a transparent background instead of black and leaves out background elements of the layout. In headless mode, frames are written with transparency."
+    )]
+    transparent: bool,
+
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = ArgAction::Set,
+        default_value_t = false,
+        help = "Shows the window without a title bar or border.")]
+    borderless: bool,
+
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = ArgAction::Set,
+        default_value_t = false,
+        help = "Keeps the window above other windows.")]
+    always_on_top: bool,
+
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = ArgAction::Set,
+        default_value_t = false,
+        help = "Passes mouse clicks through the window to whatever is below it."
+    )]
+    click_through: bool,
+}
+
+fn exit_with_error(kind: ErrorKind, message: String) -> ! {
+    Args::command().error(kind, message).exit()
+}
+
+fn invalid_config(message: String) -> ! {
+    exit_with_error(
+        ErrorKind::InvalidValue,
+        format!("invalid config: {message}"),
+    )
+}
+
+/// Replaces `setting` with `value` unless it was given on the command line.
+fn merge<T>(setting: &mut T, given: bool, value: Option<T>) {
+    if let (false, Some(value)) = (given, value) {
+        *setting = value;
+    }
+}
+
+/// Accepts IPv6 addresses in brackets as well, e.g. `[::1]`.
+fn parse_host(s: &str) -> Result<String, String> {
+    let host = s
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(s);
+    if host.is_empty() {
+        return Err("host must not be empty".to_owned());
+    }
+
+    Ok(host.to_owned())
+}
+
+/// Parses a finite number greater than 0, e.g. a rate or an interval.
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let value = s
+        .parse::<f64>()
+        .map_err(|e| format!("invalid number `{s}`: {e}"))?;
+    check_positive(value)
+}
+
+fn check_positive(value: f64) -> Result<f64, String> {
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err("must be a number greater than 0".to_owned())
+    }
+}
+
+/// Parses a number from 0 to 1, e.g. an opacity.
+fn parse_fraction(s: &str) -> Result<f32, String> {
+    let value = s
+        .parse::<f32>()
+        .map_err(|e| format!("invalid number `{s}`: {e}"))?;
+    check_fraction(value)
+}
+
+fn check_fraction(value: f32) -> Result<f32, String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err("must be a number from 0 to 1".to_owned())
+    }
+}
+
+fn parse_size(s: &str) -> Result<PhysicalSize<u32>, String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got `{s}`"))?;
+    let parse = |n: &str| {
+        n.parse::<u32>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("invalid dimension `{n}`"))
+    };
+
+    Ok(PhysicalSize::new(parse(width)?, parse(height)?))
+}
+
+/// Reads inputs from an [`InputSource`], recording them if requested.
+struct Feed {
+    source: Box<dyn InputSource>,
+    recorder: Option<Recorder>,
+    samples: Vec<Sample>,
+    input: Input,
+    latency: LatencyStats,
+    presses: PressStats,
+}
+
+impl Feed {
+    fn poll(&mut self, elapsed: Duration) {
+        self.samples.clear();
+        self.source.poll(elapsed, &mut self.samples);
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = self.samples.iter().try_for_each(|s| recorder.record(s)) {
+                log::error!("Failed to record input, stopping recording: {e}");
+                self.recorder = None;
+            }
+        }
+
+        for sample in &self.samples {
+            self.presses.record(sample.time, &sample.input);
+        }
+
+        if let Some(sample) = self.samples.last() {
+            self.input = sample.input.clone();
+        }
+    }
+
+    fn player(&self) -> Player<'_> {
+        Player {
+            input: &self.input,
+            samples: &self.samples,
+            status: self.status(),
+        }
+    }
+
+    fn status(&self) -> Status {
+        self.source.status()
+    }
+
+    fn stats(&self) -> PlayerStats {
+        PlayerStats {
+            packets: self.source.packet_stats(),
+            latency: self.latency.clone(),
+            presses: self.presses.counts,
+            apm: self.presses.apm(Instant::now()),
+        }
+    }
+
+    /// Records the latency of the input shown in the frame presented at `presented`, if it changed.
+    fn record_present(&mut self, presented: Instant) {
+        if let Some(sample) = self.samples.last() {
+            self.latency
+                .record(presented.saturating_duration_since(sample.time));
+        }
+    }
+
+    fn is_finished(&mut self) -> bool {
+        self.source.as_replay().is_some_and(|r| r.is_finished())
+    }
+}
+
+impl Drop for Feed {
+    fn drop(&mut self) {
+        if let Some(Err(e)) = self.recorder.as_mut().map(Recorder::flush) {
+            log::error!("Failed to flush recording: {e}");
+        }
+    }
+}
+
+/// Logs every player's statistics at a fixed interval.
+struct StatsLog {
+    interval: Duration,
+    last: Instant,
+}
+
+impl StatsLog {
+    fn poll(&mut self, feeds: &mut [Feed]) {
+        if self.last.elapsed() < self.interval {
+            return;
+        }
+        self.last = Instant::now();
+
+        for (player, feed) in feeds.iter_mut().enumerate() {
+            log::info!("Player {}: {}", player + 1, feed.stats());
+            feed.latency.reset_max();
+        }
+    }
+}
+
+/// Themes or layouts that hotkeys cycle through.
+struct Selection<T> {
+    choices: Vec<T>,
+    index: usize,
+    /// Value of `--theme` or `--layout` the initial choice was selected with, saved in the session
+    /// while it is selected since it may not be available by name.
+    initial: Option<String>,
+    initial_index: usize,
+}
+
+impl<T: Named + PartialEq> Selection<T> {
+    /// Selects `selected`, adding it to `choices` if it isn't one of them.
+    fn new(mut choices: Vec<T>, selected: T, initial: Option<String>) -> Self {
+        let index = match choices.iter().position(|choice| *choice == selected) {
+            Some(index) => index,
+            None => {
+                choices.push(selected);
+                choices.len() - 1
+            }
+        };
+
+        Self {
+            choices,
+            index,
+            initial,
+            initial_index: index,
+        }
+    }
+
+    fn current(&self) -> &T {
+        &self.choices[self.index]
+    }
+
+    /// Selects the next choice, or the previous one if `backwards`, wrapping around. Choices that
+    /// `switch` fails for are skipped, keeping the current choice if it fails for every other one.
+    fn cycle(&mut self, backwards: bool, mut switch: impl FnMut(&T) -> bool) {
+        let len = self.choices.len();
+        for step in 1..len {
+            let index = if backwards {
+                (self.index + len - step) % len
+            } else {
+                (self.index + step) % len
+            };
+            if switch(&self.choices[index]) {
+                self.index = index;
+                return;
+            }
+        }
+    }
+
+    /// Returns the value of `--theme` or `--layout` selecting the current choice.
+    fn selector(&self) -> Option<String> {
+        if self.index == self.initial_index {
+            self.initial.clone()
+        } else {
+            Some(self.current().name().to_owned())
+        }
+    }
 }
 
 struct App<'a> {
     version_string: String,
     icon: Option<Icon>,
     custom_shader: Option<String>,
-    context: Arc<SocketContext>,
-    socket_thread: Option<JoinHandle<()>>,
+    shader_watcher: Option<FileWatcher>,
+    themes: Selection<Theme>,
+    layouts: Selection<NamedLayout>,
+    layout: Option<Layout>,
+    arrangement: Arrangement,
+    feeds: Vec<Feed>,
+    show_stats: bool,
+    stats_log: Option<StatsLog>,
+    trail_length: u16,
+    trail_fade: f32,
+    timeline_duration: f32,
+    game_frames: Option<f64>,
+    window_args: WindowArgs,
+    /// Geometry of the window, restored from the last session and saved when it is closed.
+    geometry: Option<WindowGeometry>,
+    modifiers: ModifiersState,
+    last_frame: Option<Instant>,
     window: Option<Arc<Window>>,
     state: Option<State<'a>>,
 }
 
 impl ApplicationHandler for App<'_> {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let (columns, rows) = self.arrangement.grid_size(self.feeds.len());
+        let grid = [columns as u32, rows as u32];
+        // The restored size is dropped when a different number of controllers is shown.
+        let size = self
+            .geometry
+            .filter(|geometry| geometry.grid == grid)
+            .map_or(
+                PhysicalSize::new(512 * grid[0], 256 * grid[1]),
+                |geometry| geometry.size.into(),
+            );
+        let mut attributes = WindowAttributes::default();
+        if let Some(position) = self.geometry.and_then(|geometry| geometry.position) {
+            attributes = attributes.with_position(PhysicalPosition::<i32>::from(position));
+        }
+
         let window = Arc::new(
             event_loop
                 .create_window(
-                    WindowAttributes::default()
+                    attributes
                         .with_title(format!("gcviewer | {}", self.version_string))
-                        .with_inner_size(winit::dpi::PhysicalSize {
-                            width: 512,
-                            height: 256,
-                        })
-                        .with_window_icon(Some(self.icon.take().unwrap())),
+                        .with_inner_size(size)
+                        .with_window_icon(Some(self.icon.take().unwrap()))
+                        .with_transparent(self.window_args.transparent)
+                        .with_decorations(!self.window_args.borderless)
+                        .with_window_level(if self.window_args.always_on_top {
+                            WindowLevel::AlwaysOnTop
+                        } else {
+                            WindowLevel::Normal
+                        }),
                 )
                 .unwrap(),
         );
+        if self.window_args.click_through {
+            if let Err(e) = window.set_cursor_hittest(false) {
+                log::warn!("Failed to make the window click-through: {e}");
+            }
+        }
 
         self.window = Some(window.clone());
         self.state = Some(pollster::block_on(State::new(
             window.clone(),
             self.custom_shader.take(),
+            self.layout.take().unwrap(),
+            self.arrangement,
         )));
+        if let Some(state) = self.state.as_mut() {
+            state.show_stats = self.show_stats;
+            state.set_stick_trail(self.trail_length.into(), self.trail_fade);
+            state.set_timeline_duration(self.timeline_duration);
+            state.set_game_frame_rate(self.game_frames);
+            state.set_transparent(self.window_args.transparent);
+        }
     }
 
     fn window_event(
@@ -122,10 +936,13 @@ impl ApplicationHandler for App<'_> {
 
         match event {
             WindowEvent::CloseRequested => {
-                self.context.stop_flag.store(true, Ordering::Release);
-                if let Some(t) = self.socket_thread.take() {
-                    mem::drop(t.join());
-                }
+                let size = window.inner_size();
+                let (columns, rows) = self.arrangement.grid_size(self.feeds.len());
+                self.geometry = Some(WindowGeometry {
+                    position: window.outer_position().ok().map(Into::into),
+                    size: [size.width, size.height],
+                    grid: [columns as u32, rows as u32],
+                });
 
                 // FUTURE(Sirius902) Explicitly drop state before exiting event loop otherwise we
                 // crash in some wayland code. Fix the surface lifetimes in [`State`] so that this won't happen?
@@ -141,14 +958,62 @@ impl ApplicationHandler for App<'_> {
             WindowEvent::ScaleFactorChanged { .. } => {
                 state.resize(window.inner_size());
             }
-            WindowEvent::RedrawRequested => {
-                {
-                    let input = self.context.input.lock().unwrap();
-                    state.update(&input);
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key,
+                        state: ElementState::Pressed,
+                        // Holding a key down would otherwise toggle or cycle on every repeat.
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                match logical_key.as_ref() {
+                    Key::Named(NamedKey::F3) => state.show_stats = !state.show_stats,
+                    Key::Named(NamedKey::F4) => {
+                        let now = Instant::now();
+                        for feed in &mut self.feeds {
+                            feed.presses.reset(now);
+                        }
+                        log::info!("Reset press counts");
+                    }
+                    Key::Named(NamedKey::F5) => {
+                        self.themes.cycle(self.modifiers.shift_key(), |theme| {
+                            let switched = switch_theme(state, theme);
+                            if switched {
+                                self.shader_watcher = theme.path().map(FileWatcher::new);
+                            }
+                            switched
+                        });
+                    }
+                    Key::Named(NamedKey::F6) => {
+                        self.layouts.cycle(self.modifiers.shift_key(), |layout| {
+                            switch_layout(state, layout)
+                        });
+                    }
+                    _ => {}
                 }
 
+                if let Some(replay) = self.feeds.iter_mut().find_map(|f| f.source.as_replay()) {
+                    match logical_key.as_ref() {
+                        Key::Named(NamedKey::Space) => replay.toggle_pause(),
+                        Key::Character(".") if replay.is_paused() => replay.step(),
+                        _ => {}
+                    }
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                let now = Instant::now();
+                let elapsed = self.last_frame.map_or(Duration::ZERO, |t| now - t);
+                self.last_frame = Some(now);
+                state.update(&poll_feeds(&mut self.feeds, elapsed));
+
                 match state.render() {
-                    Ok(()) => {}
+                    Ok(()) => record_frame_stats(&mut self.feeds, state, self.stats_log.as_mut()),
                     Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
                     Err(wgpu::SurfaceError::OutOfMemory) => event_loop.exit(),
                     Err(e) => log::error!("{:?}", e),
@@ -161,6 +1026,10 @@ impl ApplicationHandler for App<'_> {
     fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
         let window = self.window.as_ref().unwrap();
 
+        if let (Some(watcher), Some(state)) = (self.shader_watcher.as_mut(), self.state.as_mut()) {
+            poll_shader_watcher(watcher, state);
+        }
+
         let PhysicalSize { width, height } = window.inner_size();
         if width != 0 && height != 0 {
             window.request_redraw();
@@ -170,62 +1039,307 @@ impl ApplicationHandler for App<'_> {
     }
 }
 
-async fn run(args: &Args, custom_shader: Option<String>) {
-    let icon = {
-        let icon = image::load_from_memory(ICON_FILE).unwrap();
-        let rgba = icon.into_rgba8();
-        let (width, height) = rgba.dimensions();
-        Icon::from_rgba(rgba.to_vec(), width, height).unwrap()
+fn poll_feeds(feeds: &mut [Feed], elapsed: Duration) -> Vec<Player<'_>> {
+    for feed in feeds.iter_mut() {
+        feed.poll(elapsed);
+    }
+    feeds.iter().map(Feed::player).collect()
+}
+
+/// Records the latency of the frame just rendered and updates the statistics overlay and log.
+fn record_frame_stats(feeds: &mut [Feed], state: &mut State, stats_log: Option<&mut StatsLog>) {
+    if let Some(presented) = state.last_present() {
+        for feed in feeds.iter_mut() {
+            feed.record_present(presented);
+        }
+    }
+
+    if state.show_stats {
+        state.update_stats(&feeds.iter().map(Feed::stats).collect::<Vec<_>>());
+    }
+
+    if let Some(stats_log) = stats_log {
+        stats_log.poll(feeds);
+    }
+}
+
+fn poll_shader_watcher(watcher: &mut FileWatcher, state: &mut State) {
+    let Some(source) = watcher.poll() else {
+        return;
     };
 
-    const SOCK_TIMEOUT: Duration = Duration::from_millis(100);
+    let path = watcher.path();
+    match shader::validate(&source, path) {
+        Ok(()) => match state.reload_shader(&source) {
+            Ok(()) => log::info!("Reloaded shader {}", path.display()),
+            Err(e) => log::error!(
+                "Failed to reload shader {}, keeping previous shader: {e}",
+                path.display()
+            ),
+        },
+        Err(e) => log::error!("Invalid shader, keeping previous shader:\n{e}"),
+    }
+}
 
-    let socket = UdpSocket::bind("0.0.0.0:0")
-        .and_then(|s| s.connect(("127.0.0.1", args.port)).map(|()| s))
-        .and_then(|s| s.set_read_timeout(Some(SOCK_TIMEOUT)).map(|()| s))
-        .and_then(|s| s.set_write_timeout(Some(SOCK_TIMEOUT)).map(|()| s))
-        .unwrap_or_else(|e| {
-            panic!(
-                "Failed to connect to input server on localhost:{}: {}",
-                args.port, e
-            );
-        });
+/// Replaces the shader with `theme`'s, returning a watcher for its file if it has one.
+/// Returns whether `theme` could be switched to, logging why not otherwise.
+fn switch_theme(state: &mut State, theme: &Theme) -> bool {
+    let result = read_theme(theme).and_then(|source| {
+        state
+            .reload_shader(source.as_deref().unwrap_or(shader::DEFAULT_SHADER))
+            .map_err(|e| e.to_string())
+    });
+    match &result {
+        Ok(()) => log::info!("Switched to theme {}", theme.name()),
+        Err(e) => log::error!(
+            "Failed to switch to theme {}, skipping it: {e}",
+            theme.name()
+        ),
+    }
+
+    result.is_ok()
+}
+
+/// Returns whether `layout` could be switched to, logging why not otherwise.
+fn switch_layout(state: &mut State, layout: &NamedLayout) -> bool {
+    let result = read_layout(layout).map(|new_layout| state.set_layout(new_layout));
+    match &result {
+        Ok(()) => log::info!("Switched to layout {}", layout.name()),
+        Err(e) => log::error!(
+            "Failed to switch to layout {}, skipping it: {e}",
+            layout.name()
+        ),
+    }
+
+    result.is_ok()
+}
 
-    let context = Arc::new(SocketContext {
-        socket,
-        input: Default::default(),
-        stop_flag: AtomicBool::new(false),
+async fn run_headless(
+    args: &Args,
+    dir: &Path,
+    feeds: &mut [Feed],
+    mut shader_watcher: Option<FileWatcher>,
+    custom_shader: Option<String>,
+    layout: Layout,
+) {
+    fs::create_dir_all(dir).unwrap_or_else(|e| {
+        panic!("Failed to create output directory {}: {e}", dir.display());
     });
 
-    let socket_thread = Some(thread::spawn(enclose!((context) move || {
-        let input_size = bincode::serialized_size(&Input::default()).unwrap();
-        let mut data = vec![0u8; input_size as usize];
-
-        while !context.stop_flag.load(Ordering::Acquire) {
-            let _ = context.socket.send(&[]);
-
-            if let Ok(received) = context.socket.recv(&mut data) {
-                if received == data.len() {
-                    let new_input = bincode::deserialize(&data).unwrap();
-                    let mut input = context.input.lock().unwrap();
-                    *input = new_input;
-                } else {
-                    log::error!("Socket received incomplete data of size {}", received);
-                    break;
+    let mut state = State::new_headless(args.size, custom_shader, layout, args.arrange).await;
+    state.show_stats = args.stats_overlay;
+    state.set_stick_trail(args.trail_length.into(), args.trail_fade);
+    state.set_timeline_duration(args.timeline_duration);
+    state.set_game_frame_rate(args.game_frames);
+    state.set_transparent(args.window.transparent);
+    let mut stats_log = create_stats_log(args);
+    let frame_time = Duration::from_secs_f64(1.0 / args.fps);
+    let start = Instant::now();
+
+    for frame in 0..args.frames.unwrap_or(u64::MAX) {
+        if let Some(watcher) = shader_watcher.as_mut() {
+            poll_shader_watcher(watcher, &mut state);
+        }
+
+        if feeds.iter_mut().any(Feed::is_finished) {
+            break;
+        }
+        state.update(&poll_feeds(feeds, frame_time));
+
+        state.render().expect("offscreen rendering cannot fail");
+        record_frame_stats(feeds, &mut state, stats_log.as_mut());
+        let image = state.read_frame().expect("Failed to read back frame");
+        let path = dir.join(format!("frame_{frame:06}.png"));
+        if let Err(e) = image.save(&path) {
+            log::error!("Failed to write {}: {e}", path.display());
+        }
+
+        let next_frame = start + frame_time.mul_f64((frame + 1) as f64);
+        thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+    }
+}
+
+fn create_stats_log(args: &Args) -> Option<StatsLog> {
+    args.stats_interval.map(|interval| StatsLog {
+        interval: Duration::from_secs_f64(interval),
+        last: Instant::now(),
+    })
+}
+
+/// Creates the input source of each controller shown.
+fn create_sources(args: &Args) -> Vec<Box<dyn InputSource>> {
+    if let Some(path) = &args.replay {
+        return vec![Box::new(
+            Replay::open(path)
+                .unwrap_or_else(|e| panic!("Failed to open replay {}: {e}", path.display()))
+                .with_speed(args.replay_speed)
+                .with_looping(args.replay_loop),
+        )];
+    }
+
+    // A single udp source shows a controller for each port.
+    let sources = if args.source == [Source::Udp] {
+        vec![Source::Udp; args.port.len()]
+    } else {
+        args.source.clone()
+    };
+
+    let count = |source| sources.iter().filter(|&&s| s == source).count();
+    let error = |message: &str| {
+        Args::command()
+            .error(ErrorKind::ArgumentConflict, message)
+            .exit()
+    };
+    if sources.len() > MAX_PLAYERS {
+        error(&format!("at most {MAX_PLAYERS} controllers can be shown"));
+    }
+    // A single port may be the default, which sources other than udp ignore.
+    if args.port.len() > 1 && count(Source::Udp) < args.port.len() {
+        error("more --port values than udp sources");
+    }
+    if count(Source::Stdin) > 1 {
+        error("stdin can only be used as a source once");
+    }
+    if args.record.is_some() && sources.len() > 1 {
+        error("--record only supports a single controller");
+    }
+
+    let mut ports = args.port.iter().copied();
+    let mut last_port: Option<u16> = None;
+    let gamepad_count = count(Source::Gamepad);
+    let mut gamepad_index = 0;
+
+    sources
+        .into_iter()
+        .map(|source| -> Box<dyn InputSource> {
+            match source {
+                Source::Udp => {
+                    // Udp sources without a port of their own use the ports after the last one.
+                    let port = ports
+                        .next()
+                        .or_else(|| last_port?.checked_add(1))
+                        .unwrap_or_else(|| {
+                            exit_with_error(
+                                ErrorKind::ValueValidation,
+                                "no --port left for every udp source".to_owned(),
+                            )
+                        });
+                    last_port = Some(port);
+                    Box::new(UdpSource::spawn(args.host.clone(), port))
+                }
+                Source::Stdin => Box::new(source::Stdin::spawn()),
+                Source::Gamepad => {
+                    let mut gamepad =
+                        GamepadSource::new(load_gamepad_mapping().unwrap_or_default())
+                            .unwrap_or_else(|e| panic!("Failed to initialize gamepad input: {e}"));
+                    if gamepad_count > 1 {
+                        gamepad = gamepad.with_index(gamepad_index);
+                        gamepad_index += 1;
+                    }
+                    Box::new(gamepad)
                 }
             }
-        }
-    })));
+        })
+        .collect()
+}
+
+async fn run(
+    args: &Args,
+    themes: Selection<Theme>,
+    layouts: Selection<NamedLayout>,
+    session: Session,
+) {
+    let mut recorder = args.record.as_ref().map(|path| {
+        Recorder::create(path).unwrap_or_else(|e| {
+            panic!("Failed to create recording {}: {e}", path.display());
+        })
+    });
+
+    let mut feeds = create_sources(args)
+        .into_iter()
+        .map(|source| Feed {
+            source,
+            recorder: recorder.take(),
+            samples: Vec::new(),
+            input: Input::default(),
+            latency: LatencyStats::default(),
+            presses: PressStats::new(Instant::now()),
+        })
+        .collect::<Vec<_>>();
+
+    let (shader_path, custom_shader) = load_theme(themes.current());
+    let shader_watcher = shader_path.map(FileWatcher::new);
+    let layout = load_layout(layouts.current());
+
+    if let Some(dir) = &args.headless {
+        run_headless(args, dir, &mut feeds, shader_watcher, custom_shader, layout).await;
+        write_press_stats(args, &feeds);
+        return;
+    }
+
+    let icon = {
+        let icon = image::load_from_memory(ICON_FILE).unwrap();
+        let rgba = icon.into_rgba8();
+        let (width, height) = rgba.dimensions();
+        Icon::from_rgba(rgba.to_vec(), width, height).unwrap()
+    };
 
     let event_loop = EventLoop::new().unwrap();
     let mut app = App {
         version_string: env!("GCVIEWER_VERSION").to_string(),
         icon: Some(icon),
         custom_shader,
-        context,
-        socket_thread,
+        shader_watcher,
+        themes,
+        layouts,
+        layout: Some(layout),
+        arrangement: args.arrange,
+        feeds,
+        show_stats: args.stats_overlay,
+        stats_log: create_stats_log(args),
+        trail_length: args.trail_length,
+        trail_fade: args.trail_fade,
+        timeline_duration: args.timeline_duration,
+        game_frames: args.game_frames,
+        window_args: args.window,
+        geometry: session.window,
+        modifiers: ModifiersState::default(),
+        last_frame: None,
         window: Default::default(),
         state: Default::default(),
     };
     let _ = event_loop.run_app(&mut app);
+    write_press_stats(args, &app.feeds);
+
+    if let Some(path) = session_path() {
+        let session = Session {
+            theme: app.themes.selector(),
+            layout: app.layouts.selector(),
+            window: app.geometry,
+            ..session
+        };
+        if let Err(e) = session.save(&path) {
+            log::error!("Failed to save session to {}: {e}", path.display());
+        }
+    }
+}
+
+fn write_press_stats(args: &Args, feeds: &[Feed]) {
+    let Some(path) = &args.press_stats else {
+        return;
+    };
+
+    let now = Instant::now();
+    let reports = feeds
+        .iter()
+        .enumerate()
+        .map(|(player, feed)| PressReport::new(player + 1, &feed.presses, now))
+        .collect::<Vec<_>>();
+    match stats::write_press_report(path, &reports) {
+        Ok(()) => log::info!("Wrote press statistics to {}", path.display()),
+        Err(e) => log::error!(
+            "Failed to write press statistics to {}: {e}",
+            path.display()
+        ),
+    }
 }
@@ -0,0 +1,50 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A theme or layout, selected by name from the built-in ones and the files in a directory.
+pub trait Named: Sized {
+    /// What is named, used in messages.
+    const KIND: &'static str;
+    /// Extension of the files, without the leading dot.
+    const EXTENSION: &'static str;
+
+    /// A file named `name`.
+    fn file(name: String, path: PathBuf) -> Self;
+
+    fn name(&self) -> &str;
+
+    /// The file at `path`, named after the file without its extension.
+    fn from_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+
+        Self::file(name, path)
+    }
+}
+
+/// Returns `builtin` followed by the files in `dir` with the extension of `T` sorted by name. A
+/// file named after a built-in one replaces it.
+pub fn discover<T: Named>(mut builtin: Vec<T>, dir: Option<&Path>) -> Vec<T> {
+    let Some(entries) = dir.and_then(|dir| fs::read_dir(dir).ok()) else {
+        return builtin;
+    };
+    let mut files = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == T::EXTENSION))
+        .map(T::from_path)
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| a.name().cmp(b.name()));
+
+    for file in files {
+        match builtin.iter_mut().find(|named| named.name() == file.name()) {
+            Some(named) => *named = file,
+            None => builtin.push(file),
+        }
+    }
+
+    builtin
+}
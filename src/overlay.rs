@@ -0,0 +1,246 @@
+use std::{mem, time::Duration};
+
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    state::MAX_PLAYERS,
+    stats::PlayerStats,
+    text::{text_size, Font, TextBuffer},
+};
+
+/// Values at which the latency, jitter and packet loss bars are full.
+const FULL_LATENCY: Duration = Duration::from_millis(50);
+const FULL_JITTER: Duration = Duration::from_millis(10);
+const FULL_LOSS: f64 = 0.05;
+
+const VERTICES_PER_QUAD: usize = 6;
+
+/// Sizes in pixels.
+const MARGIN: f32 = 8.0;
+const BAR_WIDTH: f32 = 120.0;
+const BAR_HEIGHT: f32 = 6.0;
+const BAR_SPACING: f32 = 2.0;
+const PLAYER_SPACING: f32 = 8.0;
+/// Height of a line of text.
+const TEXT_SIZE: f32 = 14.0;
+const TEXT_PADDING: f32 = 2.0;
+const LINE_SPACING: f32 = 2.0;
+
+const BACKGROUND_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 0.8];
+const GOOD_COLOR: [f32; 4] = [0.2, 0.8, 0.3, 1.0];
+const WARNING_COLOR: [f32; 4] = [0.9, 0.8, 0.2, 1.0];
+const BAD_COLOR: [f32; 4] = [0.9, 0.2, 0.2, 1.0];
+const TEXT_COLOR: [f32; 4] = [0.95, 0.95, 0.95, 1.0];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct OverlayVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+impl OverlayVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4];
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Debug overlay drawing bars for each player's input latency, packet jitter and packet loss, from
+/// top to bottom, followed by their actions per minute and press counts.
+pub struct Overlay {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    text: TextBuffer,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("overlay.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[OverlayVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let vertex_buffer = Self::create_vertex_buffer(
+            device,
+            &[<OverlayVertex as bytemuck::Zeroable>::zeroed(); VERTICES_PER_QUAD],
+        );
+
+        Self {
+            pipeline,
+            vertex_buffer,
+            num_vertices: 0,
+            text: TextBuffer::new(),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: PhysicalSize<u32>,
+        stats: &[PlayerStats],
+    ) {
+        let to_ndc = |x: f32, y: f32| {
+            [
+                x / size.width as f32 * 2.0 - 1.0,
+                1.0 - y / size.height as f32 * 2.0,
+            ]
+        };
+        let quad = |vertices: &mut Vec<OverlayVertex>, x: f32, y: f32, w: f32, h: f32, color| {
+            let [left, top] = to_ndc(x, y);
+            let [right, bottom] = to_ndc(x + w, y + h);
+            vertices.extend(
+                [
+                    [left, top],
+                    [left, bottom],
+                    [right, top],
+                    [right, top],
+                    [left, bottom],
+                    [right, bottom],
+                ]
+                .map(|position| OverlayVertex { position, color }),
+            );
+        };
+
+        // Maps text units to pixels from the top left corner, then to clip space.
+        let text_transform = |x: f32, y: f32| {
+            cgmath::Matrix4::from_translation(cgmath::vec3(-1.0, 1.0, 0.0))
+                * cgmath::Matrix4::from_nonuniform_scale(
+                    2.0 / size.width as f32,
+                    -2.0 / size.height as f32,
+                    1.0,
+                )
+                * cgmath::Matrix4::from_translation(cgmath::vec3(x, y, 0.0))
+                * cgmath::Matrix4::from_nonuniform_scale(TEXT_SIZE, -TEXT_SIZE, 1.0)
+        };
+
+        let mut vertices = Vec::new();
+        self.text.clear();
+        let mut y = MARGIN;
+        for player in stats.iter().take(MAX_PLAYERS) {
+            let packets = player.packets.as_ref();
+            let bars = [
+                player.latency.average.as_secs_f64() / FULL_LATENCY.as_secs_f64(),
+                packets.map_or(0.0, |p| p.jitter.as_secs_f64() / FULL_JITTER.as_secs_f64()),
+                packets.map_or(0.0, |p| p.loss() / FULL_LOSS),
+            ];
+
+            for fill in bars {
+                let fill = fill.clamp(0.0, 1.0) as f32;
+                let color = match fill {
+                    f if f < 0.5 => GOOD_COLOR,
+                    f if f < 0.8 => WARNING_COLOR,
+                    _ => BAD_COLOR,
+                };
+                quad(
+                    &mut vertices,
+                    MARGIN,
+                    y,
+                    BAR_WIDTH,
+                    BAR_HEIGHT,
+                    BACKGROUND_COLOR,
+                );
+                quad(
+                    &mut vertices,
+                    MARGIN,
+                    y,
+                    BAR_WIDTH * fill,
+                    BAR_HEIGHT,
+                    color,
+                );
+                y += BAR_HEIGHT + BAR_SPACING;
+            }
+
+            let mut lines = vec![format!(
+                "APM {:.0}  Presses {}",
+                player.apm,
+                player.presses.total()
+            )];
+            // Split the press counts over two lines to keep the overlay narrow.
+            let counts = player
+                .presses
+                .iter()
+                .map(|(name, count)| format!("{name} {count}"))
+                .collect::<Vec<_>>();
+            let (first, second) = counts.split_at(counts.len() / 2 + 1);
+            lines.push(first.join("  "));
+            lines.push(second.join("  "));
+
+            for line in lines {
+                y += LINE_SPACING;
+                quad(
+                    &mut vertices,
+                    MARGIN - TEXT_PADDING,
+                    y,
+                    text_size(&line).x * TEXT_SIZE + TEXT_PADDING * 2.0,
+                    TEXT_SIZE,
+                    BACKGROUND_COLOR,
+                );
+                self.text.push(&line, text_transform(MARGIN, y), TEXT_COLOR);
+                y += TEXT_SIZE;
+            }
+            y += PLAYER_SPACING;
+        }
+
+        let contents: &[u8] = bytemuck::cast_slice(&vertices);
+        if contents.len() as wgpu::BufferAddress > self.vertex_buffer.size() {
+            self.vertex_buffer = Self::create_vertex_buffer(device, &vertices);
+        } else if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, contents);
+        }
+        self.num_vertices = vertices.len() as u32;
+        self.text.upload(device, queue);
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, vertices: &[OverlayVertex]) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, font: &Font) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+        font.draw(render_pass, &self.text);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use gcinput::Input;
+use serde::{Deserialize, Serialize};
+
+use crate::source::Sample;
+
+/// Identifies an input log. Followed by a little-endian `u32` format version.
+pub const MAGIC: [u8; 4] = *b"GCVR";
+pub const VERSION: u32 = 1;
+
+/// An input log entry. Logs are a header followed by bincode encoded entries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Time since the recording started.
+    pub time: Duration,
+    pub input: Input,
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, sample: &Sample) -> bincode::Result<()> {
+        let entry = Entry {
+            time: sample.time.saturating_duration_since(self.start),
+            input: sample.input.clone(),
+        };
+        bincode::serialize_into(&mut self.writer, &entry)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads every entry of an input log written by [`Recorder`].
+pub fn read_log(path: impl AsRef<Path>) -> io::Result<Vec<Entry>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    if header[..4] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a gcviewer input log",
+        ));
+    }
+    let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+    if version != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported input log version {version}"),
+        ));
+    }
+
+    let mut entries = Vec::new();
+    loop {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => match *e {
+                // A recording cut short may end in a partial entry, so treat any EOF as the end.
+                bincode::ErrorKind::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                e => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            },
+        }
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,114 @@
+use std::{io, mem, path::Path, time::Duration};
+
+use crate::record::{self, Entry};
+
+/// Amount of log time skipped by [`Replay::step`], one frame at 60 Hz.
+pub const FRAME_STEP: Duration = Duration::from_nanos(16_666_667);
+
+/// Plays back an input log recorded with [`record::Recorder`] at its original timing.
+pub struct Replay {
+    entries: Vec<Entry>,
+    /// Index of the next entry to play.
+    next: usize,
+    /// Entries played by [`Replay::step`] since the last [`Replay::advance_with`].
+    stepped: Vec<Entry>,
+    position: Duration,
+    speed: f64,
+    looping: bool,
+    paused: bool,
+}
+
+impl Replay {
+    pub fn new(entries: Vec<Entry>) -> Self {
+        Self {
+            entries,
+            next: 0,
+            stepped: Vec::new(),
+            position: Duration::ZERO,
+            speed: 1.0,
+            looping: false,
+            paused: false,
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        record::read_log(path).map(Self::new)
+    }
+
+    /// Sets the playback speed multiplier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is negative or not finite.
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        assert!(
+            speed.is_finite() && speed >= 0.0,
+            "replay speed must be finite and not negative, got {speed}"
+        );
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.entries.last().map_or(Duration::ZERO, |e| e.time)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.position >= self.duration()
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Advances playback by `elapsed` scaled by the playback speed, unless paused, calling `f`
+    /// with every entry played since the last call, in order.
+    pub fn advance_with(&mut self, elapsed: Duration, mut f: impl FnMut(&Entry)) {
+        self.stepped.drain(..).for_each(|entry| f(&entry));
+        if !self.paused {
+            self.seek(self.position + elapsed.mul_f64(self.speed), f);
+        }
+    }
+
+    /// Advances playback by a single [`FRAME_STEP`], even while paused. The entries played are
+    /// passed on by the next [`Replay::advance_with`].
+    pub fn step(&mut self) {
+        let mut stepped = mem::take(&mut self.stepped);
+        self.seek(self.position + FRAME_STEP, |entry| {
+            stepped.push(entry.clone())
+        });
+        self.stepped = stepped;
+    }
+
+    fn seek(&mut self, position: Duration, mut f: impl FnMut(&Entry)) {
+        let duration = self.duration();
+
+        self.position = if position <= duration {
+            position
+        } else if self.looping && !duration.is_zero() {
+            self.entries[self.next..].iter().for_each(&mut f);
+            self.next = 0;
+            Duration::from_nanos((position.as_nanos() % duration.as_nanos()) as u64)
+        } else {
+            duration
+        };
+
+        while let Some(entry) = self
+            .entries
+            .get(self.next)
+            .filter(|e| e.time <= self.position)
+        {
+            f(entry);
+            self.next += 1;
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, layout::Arrangement, source::Source};
+
+/// Settings remembered between runs, restored unless given on the command line.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub source: Option<Vec<Source>>,
+    pub host: Option<String>,
+    pub port: Option<Vec<u16>>,
+    pub arrange: Option<Arrangement>,
+    pub theme: Option<String>,
+    pub layout: Option<String>,
+    pub window: Option<WindowGeometry>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Position of the window's outer top left corner, not known on platforms such as Wayland.
+    pub position: Option<[i32; 2]>,
+    /// Size of the window's contents.
+    pub size: [u32; 2],
+    /// Columns and rows of controllers shown at that size.
+    #[serde(default)]
+    pub grid: [u32; 2],
+}
+
+impl Session {
+    /// Reads the session saved at `path`. A missing or invalid session is treated as empty.
+    pub fn load(path: &Path) -> Self {
+        let Ok(s) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+
+        toml::from_str(&s)
+            .inspect_err(|e| log::error!("Failed to parse {}, ignoring it: {e}", path.display()))
+            .unwrap_or_default()
+    }
+
+    /// Leaves out the settings that `config` sets, so that they are taken from it instead. The
+    /// host and ports are left out together with the sources they were used with.
+    pub fn forget(&mut self, config: &Config) {
+        if config.source.is_some() || config.host.is_some() || config.port.is_some() {
+            self.source = None;
+            self.host = None;
+            self.port = None;
+        }
+        if config.arrange.is_some() {
+            self.arrange = None;
+        }
+        if config.theme.is_some() {
+            self.theme = None;
+        }
+        if config.layout.is_some() {
+            self.layout = None;
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let s = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, s)
+    }
+}
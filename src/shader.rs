@@ -0,0 +1,61 @@
+use std::{error::Error, fmt, path::Path};
+
+use naga::{
+    valid::{Capabilities, ValidationFlags, Validator},
+    ShaderStage,
+};
+
+pub const DEFAULT_SHADER: &str = include_str!("shader.wgsl");
+
+const ENTRY_POINTS: [(&str, ShaderStage); 2] = [
+    ("vs_main", ShaderStage::Vertex),
+    ("fs_main", ShaderStage::Fragment),
+];
+
+#[derive(Debug)]
+pub enum ShaderError {
+    /// The shader failed to parse or validate. The report names the file, line and column.
+    Invalid(String),
+    MissingEntryPoint {
+        path: String,
+        name: &'static str,
+        stage: ShaderStage,
+    },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(report) => write!(f, "{}", report.trim_end()),
+            Self::MissingEntryPoint { path, name, stage } => {
+                write!(f, "{path}: missing {stage:?} entry point `{name}`")
+            }
+        }
+    }
+}
+
+impl Error for ShaderError {}
+
+/// Checks that `source` is valid WGSL and has the entry points gcviewer renders with.
+pub fn validate(source: &str, path: &Path) -> Result<(), ShaderError> {
+    let path = path.display().to_string();
+
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| ShaderError::Invalid(e.emit_to_string_with_path(source, &path)))?;
+
+    Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| ShaderError::Invalid(e.emit_to_string_with_path(source, &path)))?;
+
+    for (name, stage) in ENTRY_POINTS {
+        if !module
+            .entry_points
+            .iter()
+            .any(|ep| ep.name == name && ep.stage == stage)
+        {
+            return Err(ShaderError::MissingEntryPoint { path, name, stage });
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::{
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use enclose::enclose;
+use gcinput::Input;
+use serde::{Deserialize, Serialize};
+
+use crate::{replay::Replay, stats::PacketStats};
+
+/// Where the inputs of a controller are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// gcfeeder's UDP input server on --port.
+    Udp,
+    /// Inputs encoded the same way as the UDP server's on standard input.
+    Stdin,
+    /// Local gamepads, mapped to GameCube controller inputs by gamepad.toml. With several gamepad
+    /// sources, each shows the next connected gamepad.
+    Gamepad,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub time: Instant,
+    pub input: Input,
+}
+
+/// Whether a source is currently receiving inputs, exposed to shaders as the `connection` uniform.
+#[repr(u32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Connected = 0,
+    #[default]
+    Disconnected = 1,
+    /// Connected, but nothing has been received for a while.
+    Stale = 2,
+}
+
+/// Something that produces controller inputs to display.
+pub trait InputSource {
+    /// Appends every input produced since the previous call to `samples`, oldest first.
+    ///
+    /// `elapsed` is the time since the previous call. Sources that play back recorded input
+    /// advance by it so that headless rendering is independent of wall-clock time. Sources may
+    /// skip inputs identical to the previous one.
+    fn poll(&mut self, elapsed: Duration, samples: &mut Vec<Sample>);
+
+    fn status(&self) -> Status {
+        Status::Connected
+    }
+
+    /// Returns statistics about received packets for sources reading from the network.
+    fn packet_stats(&self) -> Option<PacketStats> {
+        None
+    }
+
+    /// Returns the replay driving this source, if any, for playback controls.
+    fn as_replay(&mut self) -> Option<&mut Replay> {
+        None
+    }
+}
+
+/// Reads inputs from standard input, encoded the same way gcfeeder sends them over UDP.
+pub struct Stdin {
+    shared: Arc<Shared>,
+}
+
+impl Stdin {
+    pub fn spawn() -> Self {
+        let shared = Arc::new(Shared::default());
+        shared.set_status(Status::Connected);
+
+        // Reading stdin blocks, so the thread is detached rather than joined when dropped.
+        thread::spawn(enclose!((shared) move || {
+            let input_size = bincode::serialized_size(&Input::default()).unwrap();
+            let mut data = vec![0u8; input_size as usize];
+            let mut stdin = io::stdin().lock();
+
+            while !shared.stop_flag.load(Ordering::Acquire) {
+                if let Err(e) = stdin.read_exact(&mut data) {
+                    if e.kind() != io::ErrorKind::UnexpectedEof {
+                        log::error!("Failed to read input from stdin: {e}");
+                    }
+                    break;
+                }
+
+                match bincode::deserialize(&data) {
+                    Ok(input) => shared.push(input),
+                    Err(e) => log::error!("Failed to decode input from stdin: {e}"),
+                }
+            }
+
+            shared.set_status(Status::Disconnected);
+        }));
+
+        Self { shared }
+    }
+}
+
+impl InputSource for Stdin {
+    fn poll(&mut self, _elapsed: Duration, samples: &mut Vec<Sample>) {
+        self.shared.drain_into(samples);
+    }
+
+    fn status(&self) -> Status {
+        self.shared.status()
+    }
+}
+
+impl Drop for Stdin {
+    fn drop(&mut self) {
+        self.shared.stop_flag.store(true, Ordering::Release);
+    }
+}
+
+impl InputSource for Replay {
+    fn poll(&mut self, elapsed: Duration, samples: &mut Vec<Sample>) {
+        let now = Instant::now();
+        self.advance_with(elapsed, |entry| {
+            samples.push(Sample {
+                time: now,
+                input: entry.input.clone(),
+            })
+        });
+    }
+
+    fn as_replay(&mut self) -> Option<&mut Replay> {
+        Some(self)
+    }
+}
+
+/// Samples produced on a background thread waiting to be polled.
+#[derive(Default)]
+pub(crate) struct Shared {
+    samples: Mutex<Vec<Sample>>,
+    status: Mutex<Status>,
+    pub(crate) packet_stats: Mutex<PacketStats>,
+    pub(crate) stop_flag: AtomicBool,
+}
+
+impl Shared {
+    pub(crate) fn push(&self, input: Input) {
+        self.samples.lock().unwrap().push(Sample {
+            time: Instant::now(),
+            input,
+        });
+    }
+
+    pub(crate) fn drain_into(&self, samples: &mut Vec<Sample>) {
+        samples.append(&mut self.samples.lock().unwrap());
+    }
+
+    pub(crate) fn status(&self) -> Status {
+        *self.status.lock().unwrap()
+    }
+
+    /// Sets the status, returning the previous one.
+    pub(crate) fn set_status(&self, status: Status) -> Status {
+        std::mem::replace(&mut self.status.lock().unwrap(), status)
+    }
+}
@@ -1,12 +1,23 @@
-use std::{sync::Arc, time};
+use std::{
+    sync::{mpsc, Arc},
+    time,
+};
 
 use gcinput::Input;
 use wgpu::util::DeviceExt;
-use winit::window::Window;
+use winit::{dpi::PhysicalSize, window::Window};
 
 use crate::{
     camera::{Camera, CameraUniform},
-    control::{Button, Control, Instance, InstanceRaw, Misc, Scale, Stick, Trigger},
+    control::{Control, Instance, InstanceRaw, Misc},
+    frames::{GameFrameUniform, GameFrames},
+    layout::{Arrangement, Layout, LAYOUT_SIZE},
+    overlay::Overlay,
+    source::{Sample, Status},
+    stats::PlayerStats,
+    text::{Font, TextBuffer},
+    timeline::{InputHistory, MAX_TIMELINE_DURATION},
+    trail::{StickHistory, TrailUniform},
     Vertex, INDICES, VERTICES,
 };
 
@@ -14,12 +25,34 @@ const BEAN_SDF_IMAGE: &[u8] = include_bytes!("../resource/sdf/bean.png");
 const Z_BUTTON_SDF_IMAGE: &[u8] = include_bytes!("../resource/sdf/z-button.png");
 const OCTAGON_SDF_IMAGE: &[u8] = include_bytes!("../resource/sdf/octagon.png");
 
+/// Most controllers shown at once, the length of the shaders' `connection` uniform.
+pub const MAX_PLAYERS: usize = 4;
+
+/// Seconds of input history shown by timelines unless set otherwise.
+pub const DEFAULT_TIMELINE_DURATION: f32 = 3.0;
+
+/// One of the controllers shown.
+pub struct Player<'s> {
+    /// Latest input received.
+    pub input: &'s Input,
+    /// Inputs received since the last update, used to quantise inputs into game frames.
+    pub samples: &'s [Sample],
+    pub status: Status,
+}
+
+enum Target<'a> {
+    Surface(wgpu::Surface<'a>),
+    /// Offscreen texture used in headless mode, read back with [`State::read_frame`].
+    Texture(wgpu::Texture),
+}
+
 pub struct State<'a> {
-    surface: wgpu::Surface<'a>,
+    target: Target<'a>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
-    pub size: winit::dpi::PhysicalSize<u32>,
+    pub size: PhysicalSize<u32>,
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
@@ -30,14 +63,42 @@ pub struct State<'a> {
     camera_buffer: wgpu::Buffer,
     resolution_buffer: wgpu::Buffer,
     time_buffer: wgpu::Buffer,
+    connection_buffer: wgpu::Buffer,
+    players_buffer: wgpu::Buffer,
+    stick_history_buffer: wgpu::Buffer,
+    trail_buffer: wgpu::Buffer,
+    input_history_buffer: wgpu::Buffer,
+    timeline_duration_buffer: wgpu::Buffer,
+    game_frames_buffer: wgpu::Buffer,
+    gates_buffer: wgpu::Buffer,
     main_bind_group: wgpu::BindGroup,
     start_time: time::Instant,
+    layout: Layout,
+    arrangement: Arrangement,
     instances: Vec<Instance>,
     instance_buffer: wgpu::Buffer,
+    stick_history: StickHistory,
+    input_history: InputHistory,
+    game_frames: Option<GameFrames>,
+    font: Font,
+    /// Text labels and readouts of the layout, for every player.
+    labels: TextBuffer,
+    overlay: Overlay,
+    /// Whether the statistics overlay is drawn.
+    pub show_stats: bool,
+    transparent: bool,
+    /// Alpha modes supported by the window's surface, empty when rendering offscreen.
+    alpha_modes: Vec<wgpu::CompositeAlphaMode>,
+    last_present: Option<time::Instant>,
 }
 
-impl State<'_> {
-    pub async fn new(window: Arc<Window>, custom_shader: Option<String>) -> Self {
+impl<'a> State<'a> {
+    pub async fn new(
+        window: Arc<Window>,
+        custom_shader: Option<String>,
+        layout: Layout,
+        arrangement: Arrangement,
+    ) -> Self {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
@@ -55,18 +116,7 @@ impl State<'_> {
             .await
             .unwrap();
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
-                    memory_hints: wgpu::MemoryHints::default(),
-                    label: None,
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let (device, queue) = Self::request_device(&adapter).await;
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -79,6 +129,86 @@ impl State<'_> {
             desired_maximum_frame_latency: 2,
         };
         surface.configure(&device, &config);
+        let alpha_modes = surface.get_capabilities(&adapter).alpha_modes;
+
+        let mut state = Self::with_target(
+            Target::Surface(surface),
+            device,
+            queue,
+            config,
+            custom_shader,
+            layout,
+            arrangement,
+        );
+        state.alpha_modes = alpha_modes;
+        state
+    }
+
+    /// Creates a [`State`] that renders to an offscreen texture instead of a window.
+    pub async fn new_headless(
+        size: PhysicalSize<u32>,
+        custom_shader: Option<String>,
+        layout: Layout,
+        arrangement: Arrangement,
+    ) -> Self {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        // Allow software adapters such as llvmpipe or lavapipe so this also runs on machines
+        // without a GPU.
+        let mut adapter = None;
+        for force_fallback_adapter in [false, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    compatible_surface: None,
+                    force_fallback_adapter,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.expect("Failed to find a graphics adapter");
+        log::info!("Using adapter {:?}", adapter.get_info());
+
+        let (device, queue) = Self::request_device(&adapter).await;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: Vec::new(),
+            desired_maximum_frame_latency: 2,
+        };
+        let texture = Self::create_target_texture(&device, &config);
+
+        Self::with_target(
+            Target::Texture(texture),
+            device,
+            queue,
+            config,
+            custom_shader,
+            layout,
+            arrangement,
+        )
+    }
+
+    fn with_target(
+        target: Target<'a>,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        custom_shader: Option<String>,
+        layout: Layout,
+        arrangement: Arrangement,
+    ) -> Self {
+        let size = PhysicalSize::new(config.width, config.height);
 
         let texture_views = [
             (BEAN_SDF_IMAGE, "bean_sdf"),
@@ -138,19 +268,10 @@ impl State<'_> {
             ..Default::default()
         });
 
-        let shader_descriptor = if let Some(custom_shader) = custom_shader {
-            wgpu::ShaderModuleDescriptor {
-                label: Some("custom_shader"),
-                source: wgpu::ShaderSource::Wgsl(custom_shader.into()),
-            }
-        } else {
-            wgpu::include_wgsl!("shader.wgsl")
-        };
-
-        let shader = device.create_shader_module(shader_descriptor);
-
         let camera = Camera {
             aspect: config.width as f32 / config.height as f32,
+            width: LAYOUT_SIZE.x,
+            height: LAYOUT_SIZE.y,
             znear: 0.1,
             zfar: 10.0,
         };
@@ -243,6 +364,60 @@ impl State<'_> {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let connection_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Connection Buffer"),
+            contents: bytemuck::cast_slice(&[Status::default() as u32; MAX_PLAYERS]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let players_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Players Buffer"),
+            contents: bytemuck::cast_slice(&[1u32]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let stick_history = StickHistory::new(0);
+        let stick_history_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Stick History Buffer"),
+            contents: bytemuck::cast_slice(&stick_history.to_raw()),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let trail_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Trail Buffer"),
+            contents: bytemuck::cast_slice(&[TrailUniform {
+                length: 0,
+                fade: 1.0,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let input_history = InputHistory::new();
+        let input_history_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Input History Buffer"),
+            contents: bytemuck::cast_slice(&input_history.to_raw()),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let timeline_duration_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Timeline Duration Buffer"),
+                contents: bytemuck::cast_slice(&[DEFAULT_TIMELINE_DURATION]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let game_frames_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Game Frames Buffer"),
+            contents: bytemuck::cast_slice(&[GameFrameUniform::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let gates_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gates Buffer"),
+            contents: bytemuck::cast_slice(&layout.gates()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         let main_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
@@ -276,6 +451,86 @@ impl State<'_> {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 10,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
                 label: Some("main_bind_group_layout"),
             });
@@ -295,6 +550,38 @@ impl State<'_> {
                     binding: 2,
                     resource: time_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: connection_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: players_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: stick_history_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: trail_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: input_history_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: timeline_duration_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: game_frames_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: gates_buffer.as_entire_binding(),
+                },
             ],
             label: Some("main_bind_group"),
         });
@@ -306,43 +593,29 @@ impl State<'_> {
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[Vertex::desc(), InstanceRaw::desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+        // Fall back to the built-in shader so a broken custom shader never leaves the window blank.
+        let render_pipeline = custom_shader
+            .and_then(|source| {
+                Self::create_shader_pipeline(
+                    &device,
+                    &render_pipeline_layout,
+                    config.format,
+                    &source,
+                )
+                .inspect_err(|e| {
+                    log::error!("Failed to create custom shader pipeline, using default: {e}")
+                })
+                .ok()
+            })
+            .unwrap_or_else(|| {
+                let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+                Self::create_render_pipeline(
+                    &device,
+                    &render_pipeline_layout,
+                    &shader,
+                    config.format,
+                )
+            });
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -356,7 +629,7 @@ impl State<'_> {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        let instances = Self::gen_instances(&Input::default());
+        let instances = layout.instances(&Input::default());
 
         let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -365,12 +638,16 @@ impl State<'_> {
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
 
+        let font = Font::new(&device, &queue, config.format);
+        let overlay = Overlay::new(&device, config.format);
+
         Self {
-            surface,
+            target,
             device,
             queue,
             config,
             size,
+            render_pipeline_layout,
             render_pipeline,
             vertex_buffer,
             index_buffer,
@@ -381,35 +658,223 @@ impl State<'_> {
             camera_buffer,
             resolution_buffer,
             time_buffer,
+            connection_buffer,
+            players_buffer,
+            stick_history_buffer,
+            trail_buffer,
+            input_history_buffer,
+            timeline_duration_buffer,
+            game_frames_buffer,
+            gates_buffer,
             main_bind_group,
             start_time,
+            layout,
+            arrangement,
             instances,
             instance_buffer,
+            stick_history,
+            input_history,
+            game_frames: None,
+            font,
+            labels: TextBuffer::new(),
+            overlay,
+            show_stats: false,
+            transparent: false,
+            alpha_modes: Vec::new(),
+            last_present: None,
         }
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            match &mut self.target {
+                Target::Surface(surface) => surface.configure(&self.device, &self.config),
+                Target::Texture(texture) => {
+                    *texture = Self::create_target_texture(&self.device, &self.config)
+                }
+            }
         }
     }
 
-    pub fn update(&mut self, input: &Input) {
-        self.instances = Self::gen_instances(input);
+    /// Replaces the render pipeline, keeping the previous one if `source` fails to compile.
+    pub fn reload_shader(&mut self, source: &str) -> Result<(), wgpu::Error> {
+        self.render_pipeline = Self::create_shader_pipeline(
+            &self.device,
+            &self.render_pipeline_layout,
+            self.config.format,
+            source,
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the layout, e.g. when switching layouts at runtime.
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.queue
+            .write_buffer(&self.gates_buffer, 0, bytemuck::cast_slice(&layout.gates()));
+        self.layout = layout;
+
+        // Sticks may have moved, so trails of their previous positions no longer line up.
+        self.stick_history = StickHistory::new(self.stick_history.length());
+    }
+
+    /// Clears to a transparent background instead of black and leaves out background elements,
+    /// e.g. for window capture. The window has to be created transparent as well.
+    pub fn set_transparent(&mut self, transparent: bool) {
+        self.transparent = transparent;
+
+        let Target::Surface(surface) = &self.target else {
+            return;
+        };
+        // Blending onto the cleared surface leaves frames with premultiplied alpha.
+        self.config.alpha_mode = if !transparent {
+            wgpu::CompositeAlphaMode::Auto
+        } else if self
+            .alpha_modes
+            .contains(&wgpu::CompositeAlphaMode::PreMultiplied)
+        {
+            wgpu::CompositeAlphaMode::PreMultiplied
+        } else {
+            log::warn!("Transparent windows are not supported, using an opaque window");
+            wgpu::CompositeAlphaMode::Auto
+        };
+        surface.configure(&self.device, &self.config);
+    }
+
+    /// Draws a trail behind each stick of its positions over the last `length` frames, up to
+    /// [`MAX_TRAIL_LENGTH`](crate::trail::MAX_TRAIL_LENGTH), each frame older point keeping `fade`
+    /// of the opacity of the one before. A `length` of 0 disables the trails.
+    pub fn set_stick_trail(&mut self, length: usize, fade: f32) {
+        self.stick_history = StickHistory::new(length);
+        self.queue.write_buffer(
+            &self.trail_buffer,
+            0,
+            bytemuck::cast_slice(&[TrailUniform {
+                length: self.stick_history.length() as u32,
+                fade,
+            }]),
+        );
+    }
+
+    /// Sets the seconds of input history shown by timelines, up to
+    /// [`MAX_TIMELINE_DURATION`].
+    pub fn set_timeline_duration(&mut self, duration: f32) {
+        let duration = duration.clamp(0.0, MAX_TIMELINE_DURATION as f32);
+        self.queue.write_buffer(
+            &self.timeline_duration_buffer,
+            0,
+            bytemuck::cast_slice(&[duration]),
+        );
+    }
+
+    /// Quantises inputs into game frames at `rate` frames per second, showing the input each game
+    /// frame saw, a frame counter and highlighting buttons held for a single frame. `None` shows
+    /// the latest input instead.
+    pub fn set_game_frame_rate(&mut self, rate: Option<f64>) {
+        self.game_frames = rate.map(GameFrames::new);
+        self.queue.write_buffer(
+            &self.game_frames_buffer,
+            0,
+            bytemuck::cast_slice(&[GameFrameUniform::default()]),
+        );
+    }
+
+    /// Updates the controllers shown, one for each player, up to [`MAX_PLAYERS`].
+    pub fn update(&mut self, players: &[Player]) {
+        let players = &players[..players.len().min(MAX_PLAYERS)];
+
+        let now = time::Instant::now();
+        let inputs = match self.game_frames.as_mut() {
+            Some(game_frames) => {
+                let samples = players.iter().map(|p| p.samples).collect::<Vec<_>>();
+                game_frames.update(now, &samples);
+                self.queue.write_buffer(
+                    &self.game_frames_buffer,
+                    0,
+                    bytemuck::cast_slice(&[game_frames.to_uniform()]),
+                );
+                (0..players.len())
+                    .map(|player| game_frames.input(player).unwrap())
+                    .collect::<Vec<_>>()
+            }
+            None => players.iter().map(|p| p.input).collect(),
+        };
+
+        self.instances.clear();
+        for (player, input) in inputs.iter().enumerate() {
+            let offset = self.arrangement.offset(player, players.len());
+            self.instances.extend(
+                self.layout
+                    .instances(input)
+                    .into_iter()
+                    .filter(|instance| {
+                        !(self.transparent
+                            && matches!(instance.control, Control::Misc(Misc::Background)))
+                    })
+                    .map(|mut instance| {
+                        instance.position += offset;
+                        instance.player = player as u32;
+                        instance
+                    }),
+            );
+        }
+
+        for instance in &self.instances {
+            if let Control::Stick { stick, position } = instance.control {
+                self.stick_history
+                    .push(instance.player as usize, stick, position);
+            }
+        }
+        self.queue.write_buffer(
+            &self.stick_history_buffer,
+            0,
+            bytemuck::cast_slice(&self.stick_history.to_raw()),
+        );
+
+        self.input_history.update(now, inputs.iter().copied());
+        self.queue.write_buffer(
+            &self.input_history_buffer,
+            0,
+            bytemuck::cast_slice(&self.input_history.to_raw()),
+        );
+
         let instance_data = self
             .instances
             .iter()
             .map(Instance::to_raw)
             .collect::<Vec<_>>();
+        let instance_data: &[u8] = bytemuck::cast_slice(&instance_data);
+        if instance_data.len() as wgpu::BufferAddress > self.instance_buffer.size() {
+            self.instance_buffer =
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Instance Buffer"),
+                        contents: instance_data,
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    });
+        } else {
+            self.queue
+                .write_buffer(&self.instance_buffer, 0, instance_data);
+        }
+
+        let mut statuses = [Status::default() as u32; MAX_PLAYERS];
+        for (status, player) in statuses.iter_mut().zip(players) {
+            *status = player.status as u32;
+        }
+        self.queue
+            .write_buffer(&self.connection_buffer, 0, bytemuck::cast_slice(&statuses));
         self.queue.write_buffer(
-            &self.instance_buffer,
+            &self.players_buffer,
             0,
-            bytemuck::cast_slice(&instance_data),
+            bytemuck::cast_slice(&[players.len() as u32]),
         );
 
+        let (columns, rows) = self.arrangement.grid_size(players.len());
+        self.camera.width = columns as f32 * LAYOUT_SIZE.x;
+        self.camera.height = rows as f32 * LAYOUT_SIZE.y;
+
         self.queue.write_buffer(
             &self.resolution_buffer,
             0,
@@ -423,6 +888,20 @@ impl State<'_> {
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
+
+        let view_proj = self.camera.build_projection_view_matrix();
+        self.labels.clear();
+        for (player, input) in inputs.iter().enumerate() {
+            let offset = self.arrangement.offset(player, players.len());
+            for label in self.layout.labels(input) {
+                let transform = view_proj
+                    * cgmath::Matrix4::from_translation(offset.extend(0.0))
+                    * label.model_matrix();
+                self.labels.push(&label.text, transform, label.color);
+            }
+        }
+        self.labels.upload(&self.device, &self.queue);
+
         self.queue.write_buffer(
             &self.time_buffer,
             0,
@@ -430,11 +909,31 @@ impl State<'_> {
         );
     }
 
+    /// Updates the statistics overlay, one entry per player.
+    pub fn update_stats(&mut self, stats: &[PlayerStats]) {
+        self.overlay
+            .update(&self.device, &self.queue, self.size, stats);
+    }
+
+    /// Returns when the last frame was presented, or submitted when rendering offscreen.
+    pub fn last_present(&self) -> Option<time::Instant> {
+        self.last_present
+    }
+
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let (output, view) = match &self.target {
+            Target::Surface(surface) => {
+                let output = surface.get_current_texture()?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(output), view)
+            }
+            Target::Texture(texture) => (
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -448,7 +947,11 @@ impl State<'_> {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        load: wgpu::LoadOp::Clear(if self.transparent {
+                            wgpu::Color::TRANSPARENT
+                        } else {
+                            wgpu::Color::BLACK
+                        }),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -457,162 +960,196 @@ impl State<'_> {
                 timestamp_writes: None,
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.main_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
+            // Layouts of only text have no controls, leaving the instance buffer empty.
+            if !self.instances.is_empty() {
+                render_pass.set_pipeline(&self.render_pipeline);
+                render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.main_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
+            }
+            self.font.draw(&mut render_pass, &self.labels);
+
+            if self.show_stats {
+                self.overlay.draw(&mut render_pass, &self.font);
+            }
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        if let Some(output) = output {
+            output.present();
+        }
+        self.last_present = Some(time::Instant::now());
 
         Ok(())
     }
 
-    fn stick_to_vec2(stick: &gcinput::Stick) -> cgmath::Vector2<f32> {
-        let f = |n: u8| ((u8::MAX - n) as f32 / u8::MAX as f32 - 0.5) * 0.6;
-        cgmath::vec2(f(stick.x), f(stick.y))
-    }
+    /// Reads back the last rendered frame with straight alpha. Returns `None` when rendering to a
+    /// window.
+    pub fn read_frame(&self) -> Option<image::RgbaImage> {
+        let Target::Texture(texture) = &self.target else {
+            return None;
+        };
 
-    fn gen_instances(input: &Input) -> Vec<Instance> {
-        vec![
-            Instance {
-                control: Control::Misc(Misc::Background),
-                position: cgmath::vec2(0.0, 0.0),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::NonUniform(2.0, 1.0),
-            },
-            Instance {
-                control: Control::Button {
-                    button: Button::A,
-                    pressed: input.button_a,
-                },
-                position: cgmath::vec2(0.5, -0.075),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.302),
-            },
-            Instance {
-                control: Control::Button {
-                    button: Button::B,
-                    pressed: input.button_b,
-                },
-                position: cgmath::vec2(0.275, -0.225),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.17),
-            },
-            Instance {
-                control: Control::Button {
-                    button: Button::X,
-                    pressed: input.button_x,
-                },
-                position: cgmath::vec2(0.75, -0.075),
-                rotation: cgmath::Deg(225.0),
-                scale: Scale::Uniform(0.275),
-            },
-            Instance {
-                control: Control::Button {
-                    button: Button::Y,
-                    pressed: input.button_y,
-                },
-                position: cgmath::vec2(0.4, 0.15),
-                rotation: cgmath::Deg(-20.0),
-                scale: Scale::Uniform(0.275),
-            },
-            Instance {
-                control: Control::Button {
-                    button: Button::Start,
-                    pressed: input.button_start,
-                },
-                position: cgmath::vec2(0.175, -0.025),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.126),
-            },
-            Instance {
-                control: Control::Button {
-                    button: Button::Z,
-                    pressed: input.button_z,
-                },
-                position: cgmath::vec2(0.685, 0.21),
-                rotation: cgmath::Deg(-80.0),
-                scale: Scale::Uniform(0.225),
-            },
-            Instance {
-                control: Control::Stick {
-                    stick: Stick::Main,
-                    position: Self::stick_to_vec2(&input.main_stick),
-                },
-                position: cgmath::vec2(-0.65, 0.0),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.565),
-            },
-            Instance {
-                control: Control::Stick {
-                    stick: Stick::C,
-                    position: Self::stick_to_vec2(&input.c_stick),
-                },
-                position: cgmath::vec2(-0.15, 0.0),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.565),
-            },
-            Instance {
-                control: Control::Trigger {
-                    trigger: Trigger::Left,
-                    fill: input.left_trigger as f32 / u8::MAX as f32,
-                    pressed: input.button_left,
-                },
-                position: cgmath::vec2(-0.65, 0.35),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.375),
-            },
-            Instance {
-                control: Control::Trigger {
-                    trigger: Trigger::Right,
-                    fill: input.right_trigger as f32 / u8::MAX as f32,
-                    pressed: input.button_right,
+        let PhysicalSize { width, height } = self.size;
+        let unpadded_bytes_per_row = width * 4;
+        let padded_bytes_per_row =
+            unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
                 },
-                position: cgmath::vec2(-0.15, 0.35),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.375),
             },
-            Instance {
-                control: Control::Button {
-                    button: Button::Up,
-                    pressed: input.button_up,
+            texture.size(),
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (tx, rx) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv().ok()?.ok()?;
+
+        let pixels = slice
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+            .copied()
+            .collect();
+        buffer.unmap();
+
+        // Blending onto the cleared target leaves colors premultiplied by alpha.
+        let mut image = image::RgbaImage::from_raw(width, height, pixels)?;
+        for image::Rgba([r, g, b, a]) in image.pixels_mut() {
+            if *a > 0 && *a < u8::MAX {
+                for channel in [r, g, b] {
+                    *channel = ((*channel as u32 * 255 + *a as u32 / 2) / *a as u32).min(255) as u8;
+                }
+            }
+        }
+        Some(image)
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::default(),
+                    memory_hints: wgpu::MemoryHints::default(),
+                    label: None,
                 },
-                position: cgmath::vec2(-0.4, -0.22),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.1),
+                None,
+            )
+            .await
+            .unwrap()
+    }
+
+    fn create_target_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Target Texture"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
             },
-            Instance {
-                control: Control::Button {
-                    button: Button::Down,
-                    pressed: input.button_down,
-                },
-                position: cgmath::vec2(-0.4, -0.38),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.1),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        })
+    }
+
+    fn create_shader_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        source: &str,
+    ) -> Result<wgpu::RenderPipeline, wgpu::Error> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("custom_shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let render_pipeline = Self::create_render_pipeline(device, layout, &shader, format);
+
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(e) => Err(e),
+            None => Ok(render_pipeline),
+        }
+    }
+
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_main"),
+                buffers: &[Vertex::desc(), InstanceRaw::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
-            Instance {
-                control: Control::Button {
-                    button: Button::Left,
-                    pressed: input.button_left,
-                },
-                position: cgmath::vec2(-0.48, -0.3),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.1),
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
             },
-            Instance {
-                control: Control::Button {
-                    button: Button::Right,
-                    pressed: input.button_right,
-                },
-                position: cgmath::vec2(-0.32, -0.3),
-                rotation: cgmath::Deg(0.0),
-                scale: Scale::Uniform(0.1),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
             },
-        ]
+            multiview: None,
+            cache: None,
+        })
     }
 }
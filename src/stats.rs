@@ -0,0 +1,288 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use gcinput::Input;
+use serde::{ser::SerializeMap, Serialize};
+
+use crate::control::{held_buttons, HELD_BUTTON_NAMES};
+
+/// Window over which the current actions per minute are measured.
+const APM_WINDOW: Duration = Duration::from_secs(60);
+/// Shortest time actions per minute are measured over, so the first press doesn't read as a huge
+/// rate.
+const MIN_APM_WINDOW: Duration = Duration::from_secs(5);
+
+/// Statistics about the packets received from an input server.
+#[derive(Debug, Default, Clone)]
+pub struct PacketStats {
+    pub received: u64,
+    /// Packets that never arrived, either skipped sequence numbers or polls without a reply.
+    pub lost: u64,
+    pub out_of_order: u64,
+    /// Smoothed variation in the time between packets, computed like RFC 3550's jitter.
+    pub jitter: Duration,
+    pub last_received: Option<Instant>,
+    last_interval: Option<Duration>,
+}
+
+impl PacketStats {
+    pub fn record_received(&mut self, time: Instant) {
+        self.received += 1;
+
+        if let Some(interval) = self
+            .last_received
+            .map(|t| time.saturating_duration_since(t))
+        {
+            if let Some(last_interval) = self.last_interval {
+                let difference = interval.abs_diff(last_interval).as_secs_f64();
+                let jitter = self.jitter.as_secs_f64();
+                self.jitter = Duration::from_secs_f64(jitter + (difference - jitter) / 16.0);
+            }
+            self.last_interval = Some(interval);
+        }
+        self.last_received = Some(time);
+    }
+
+    pub fn record_lost(&mut self, count: u64) {
+        self.lost += count;
+    }
+
+    pub fn record_out_of_order(&mut self) {
+        self.out_of_order += 1;
+    }
+
+    /// Fraction of packets lost, from 0 to 1.
+    pub fn loss(&self) -> f64 {
+        let total = self.received + self.lost;
+        if total == 0 {
+            0.0
+        } else {
+            self.lost as f64 / total as f64
+        }
+    }
+}
+
+/// Time from receiving an input to presenting the first frame showing it.
+#[derive(Debug, Default, Clone)]
+pub struct LatencyStats {
+    pub last: Option<Duration>,
+    /// Exponential moving average of the latency.
+    pub average: Duration,
+    /// Highest latency since the last call to [`LatencyStats::reset_max`].
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, latency: Duration) {
+        self.average = match self.last {
+            Some(_) => Duration::from_secs_f64(
+                self.average.as_secs_f64()
+                    + (latency.as_secs_f64() - self.average.as_secs_f64()) / 16.0,
+            ),
+            None => latency,
+        };
+        self.max = self.max.max(latency);
+        self.last = Some(latency);
+    }
+
+    pub fn reset_max(&mut self) {
+        self.max = Duration::ZERO;
+    }
+}
+
+/// Counts of presses of every button and full presses of the triggers, in the order of
+/// [`HELD_BUTTON_NAMES`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PressCounts(pub [u64; HELD_BUTTON_NAMES.len()]);
+
+impl PressCounts {
+    pub fn total(&self) -> u64 {
+        self.0.iter().sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        HELD_BUTTON_NAMES.into_iter().zip(self.0.iter().copied())
+    }
+}
+
+impl Serialize for PressCounts {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, count) in self.iter() {
+            map.serialize_entry(&name.to_lowercase(), &count)?;
+        }
+        map.end()
+    }
+}
+
+/// Counts the presses of a controller and measures its actions per minute, every press being an
+/// action.
+#[derive(Debug, Clone)]
+pub struct PressStats {
+    pub counts: PressCounts,
+    /// When counting started or was last reset.
+    pub since: Instant,
+    held: u32,
+    /// Times of the actions within the last [`APM_WINDOW`].
+    recent: VecDeque<Instant>,
+}
+
+impl PressStats {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            counts: PressCounts::default(),
+            since: now,
+            held: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Counts the buttons pressed in `input` that weren't held in the previous one.
+    pub fn record(&mut self, time: Instant, input: &Input) {
+        let held = held_buttons(input);
+        let pressed = held & !self.held;
+        self.held = held;
+
+        for (bit, count) in self.counts.0.iter_mut().enumerate() {
+            if pressed & (1 << bit) != 0 {
+                *count += 1;
+                self.recent.push_back(time);
+            }
+        }
+
+        while self
+            .recent
+            .front()
+            .is_some_and(|&t| time.saturating_duration_since(t) > APM_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Clears the counts, keeping track of the buttons currently held.
+    pub fn reset(&mut self, now: Instant) {
+        self.counts = PressCounts::default();
+        self.since = now;
+        self.recent.clear();
+    }
+
+    /// Actions per minute over the last minute.
+    pub fn apm(&self, now: Instant) -> f64 {
+        let window = self.window(now).min(APM_WINDOW);
+        let actions = self
+            .recent
+            .iter()
+            .filter(|&&t| now.saturating_duration_since(t) <= window)
+            .count();
+        actions as f64 / window.as_secs_f64() * 60.0
+    }
+
+    /// Actions per minute since counting started or was last reset.
+    pub fn average_apm(&self, now: Instant) -> f64 {
+        self.counts.total() as f64 / self.window(now).as_secs_f64() * 60.0
+    }
+
+    fn window(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.since)
+            .max(MIN_APM_WINDOW)
+    }
+}
+
+/// Press statistics of a controller over a practice session, written with [`write_press_report`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PressReport {
+    pub player: usize,
+    pub seconds: f64,
+    pub actions: u64,
+    pub apm: f64,
+    #[serde(flatten)]
+    pub presses: PressCounts,
+}
+
+impl PressReport {
+    pub fn new(player: usize, stats: &PressStats, now: Instant) -> Self {
+        Self {
+            player,
+            seconds: now.saturating_duration_since(stats.since).as_secs_f64(),
+            actions: stats.counts.total(),
+            apm: stats.average_apm(now),
+            presses: stats.counts,
+        }
+    }
+}
+
+/// Writes `reports` to `path` as JSON if it ends in `.json` and as CSV otherwise, one row per
+/// controller.
+pub fn write_press_report(path: &Path, reports: &[PressReport]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+    {
+        serde_json::to_writer_pretty(&mut writer, reports)?;
+        writeln!(writer)?;
+    } else {
+        write!(writer, "player,seconds,actions,apm")?;
+        for name in HELD_BUTTON_NAMES {
+            write!(writer, ",{}", name.to_lowercase())?;
+        }
+        writeln!(writer)?;
+
+        for report in reports {
+            write!(
+                writer,
+                "{},{:.3},{},{:.1}",
+                report.player, report.seconds, report.actions, report.apm
+            )?;
+            for (_, count) in report.presses.iter() {
+                write!(writer, ",{count}")?;
+            }
+            writeln!(writer)?;
+        }
+    }
+
+    writer.flush()
+}
+
+/// Statistics of a single controller shown.
+#[derive(Debug, Clone)]
+pub struct PlayerStats {
+    /// `None` for sources that don't receive packets, e.g. replays.
+    pub packets: Option<PacketStats>,
+    pub latency: LatencyStats,
+    pub presses: PressCounts,
+    /// Actions per minute over the last minute.
+    pub apm: f64,
+}
+
+impl fmt::Display for PlayerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(packets) = &self.packets {
+            write!(
+                f,
+                "{} packets, {} lost ({:.1}%), {} out of order, jitter {:.2} ms, ",
+                packets.received,
+                packets.lost,
+                packets.loss() * 100.0,
+                packets.out_of_order,
+                packets.jitter.as_secs_f64() * 1000.0,
+            )?;
+        }
+
+        write!(
+            f,
+            "latency {:.2} ms average, {:.2} ms max, {} presses, {:.0} APM",
+            self.latency.average.as_secs_f64() * 1000.0,
+            self.latency.max.as_secs_f64() * 1000.0,
+            self.presses.total(),
+            self.apm,
+        )
+    }
+}
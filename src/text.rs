@@ -0,0 +1,278 @@
+use std::mem;
+
+use cgmath::{Matrix4, Vector2, Vector4};
+use wgpu::util::DeviceExt;
+
+/// Signed distance field of DejaVu Sans Mono Bold's printable ASCII characters, in a grid of
+/// [`ATLAS_COLUMNS`] by [`ATLAS_ROWS`] equally sized cells starting at [`FIRST_CHAR`].
+const FONT_SDF_IMAGE: &[u8] = include_bytes!("../resource/sdf/font.png");
+
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 6;
+const FIRST_CHAR: u32 = ' ' as u32;
+/// Cell drawn for characters outside the atlas.
+const MISSING_CHAR: u32 = 0x7F;
+/// Width of a cell relative to its height, which is the height of a line.
+const CELL_ASPECT: f32 = 40.0 / 64.0;
+/// Distance between the start of consecutive characters relative to the height of a line. Glyphs
+/// are centered in their cell.
+pub const GLYPH_ADVANCE: f32 = 28.9 / 64.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+impl TextVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Pipeline drawing text from an SDF font atlas, shared by everything that draws text.
+pub struct Font {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Text to be drawn by a [`Font`], rebuilt whenever it changes.
+#[derive(Default)]
+pub struct TextBuffer {
+    vertices: Vec<TextVertex>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    num_vertices: u32,
+}
+
+impl Font {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let img = image::load_from_memory(FONT_SDF_IMAGE).expect("failed to decode font image");
+        let sdf = img.into_luma8();
+        let dimensions = sdf.dimensions();
+
+        let texture_size = wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some("font_sdf"),
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &sdf,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(dimensions.0),
+                rows_per_image: Some(dimensions.1),
+            },
+            texture_size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("font_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+            label: Some("font_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("text.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Text Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[TextVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+        }
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, text: &TextBuffer) {
+        let Some(vertex_buffer) = &text.vertex_buffer else {
+            return;
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..text.num_vertices, 0..1);
+    }
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes all text, to be rebuilt with [`TextBuffer::push`].
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    /// Adds `text` with its lines one unit high, starting at the origin and extending right and
+    /// down, see [`text_size`]. `transform` maps these units to clip space.
+    pub fn push(&mut self, text: &str, transform: Matrix4<f32>, color: [f32; 4]) {
+        let to_clip = |x: f32, y: f32| {
+            let position = transform * Vector4::new(x, y, 0.0, 1.0);
+            [position.x / position.w, position.y / position.w]
+        };
+
+        for (line, text) in text.lines().enumerate() {
+            let top = -(line as f32);
+            for (column, c) in text.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+
+                let cell = match c as u32 {
+                    c @ FIRST_CHAR..MISSING_CHAR => c - FIRST_CHAR,
+                    _ => MISSING_CHAR - FIRST_CHAR,
+                };
+                let u = (cell % ATLAS_COLUMNS) as f32 / ATLAS_COLUMNS as f32;
+                let v = (cell / ATLAS_COLUMNS) as f32 / ATLAS_ROWS as f32;
+                let (du, dv) = (1.0 / ATLAS_COLUMNS as f32, 1.0 / ATLAS_ROWS as f32);
+
+                let left = column as f32 * GLYPH_ADVANCE - (CELL_ASPECT - GLYPH_ADVANCE) / 2.0;
+                let right = left + CELL_ASPECT;
+                let bottom = top - 1.0;
+
+                self.vertices.extend(
+                    [
+                        ([left, top], [u, v]),
+                        ([left, bottom], [u, v + dv]),
+                        ([right, top], [u + du, v]),
+                        ([right, top], [u + du, v]),
+                        ([left, bottom], [u, v + dv]),
+                        ([right, bottom], [u + du, v + dv]),
+                    ]
+                    .map(|([x, y], tex_coords)| TextVertex {
+                        position: to_clip(x, y),
+                        tex_coords,
+                        color,
+                    }),
+                );
+            }
+        }
+    }
+
+    /// Uploads the text pushed since the last [`TextBuffer::clear`] to be drawn.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let contents: &[u8] = bytemuck::cast_slice(&self.vertices);
+        match &self.vertex_buffer {
+            Some(buffer) if contents.len() as wgpu::BufferAddress <= buffer.size() => {
+                queue.write_buffer(buffer, 0, contents);
+            }
+            _ if self.vertices.is_empty() => {}
+            _ => {
+                self.vertex_buffer = Some(device.create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Text Vertex Buffer"),
+                        contents,
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    },
+                ));
+            }
+        }
+        self.num_vertices = self.vertices.len() as u32;
+    }
+}
+
+/// Returns the width and height of `text` in units of its line height.
+pub fn text_size(text: &str) -> Vector2<f32> {
+    let columns = text.lines().map(|line| line.chars().count()).max();
+    cgmath::vec2(
+        columns.unwrap_or(0) as f32 * GLYPH_ADVANCE,
+        text.lines().count() as f32,
+    )
+}
@@ -0,0 +1,34 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var s_font: sampler;
+@group(0) @binding(1)
+var t_font: texture_2d<f32>;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dist = textureSample(t_font, s_font, in.tex_coords).r;
+    // Antialias the edge over about a pixel whatever size the text is drawn at.
+    let width = max(fwidth(dist) * 0.5, 0.001);
+    let alpha = smoothstep(0.5 - width, 0.5 + width, dist);
+    return vec4<f32>(in.color.rgb, in.color.a * alpha);
+}
@@ -0,0 +1,79 @@
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    named::{self, Named},
+    shader::DEFAULT_SHADER,
+};
+
+pub const DEFAULT_THEME: &str = "default";
+
+/// Themes compiled into gcviewer, shown before user themes.
+const BUILTIN_THEMES: [(&str, &str); 3] = [
+    (DEFAULT_THEME, DEFAULT_SHADER),
+    ("cotton-candy", include_str!("../theme/cotton-candy.wgsl")),
+    ("rainbow", include_str!("../theme/rainbow.wgsl")),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Theme {
+    Builtin {
+        name: &'static str,
+        source: &'static str,
+    },
+    /// A shader file, named after the file without its extension.
+    File { name: String, path: PathBuf },
+}
+
+impl Named for Theme {
+    const KIND: &'static str = "theme";
+    const EXTENSION: &'static str = "wgsl";
+
+    fn file(name: String, path: PathBuf) -> Self {
+        Self::File { name, path }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Builtin { name, .. } => name,
+            Self::File { name, .. } => name,
+        }
+    }
+}
+
+impl Theme {
+    /// Returns the path of the shader, or `None` for built-in themes.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Builtin { .. } => None,
+            Self::File { path, .. } => Some(path),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        matches!(
+            self,
+            Self::Builtin {
+                name: DEFAULT_THEME,
+                ..
+            }
+        )
+    }
+
+    pub fn load(&self) -> io::Result<Cow<'static, str>> {
+        match self {
+            Self::Builtin { source, .. } => Ok(Cow::Borrowed(source)),
+            Self::File { path, .. } => fs::read_to_string(path).map(Cow::Owned),
+        }
+    }
+}
+
+/// Returns the built-in themes followed by the `.wgsl` files in `dir` sorted by name. A file named
+/// after a built-in theme replaces it.
+pub fn discover(dir: Option<&Path>) -> Vec<Theme> {
+    let builtin = BUILTIN_THEMES.map(|(name, source)| Theme::Builtin { name, source });
+    named::discover(builtin.to_vec(), dir)
+}
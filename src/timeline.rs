@@ -0,0 +1,99 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use gcinput::Input;
+
+use crate::{control::held_buttons, state::MAX_PLAYERS};
+
+/// Samples of input history per second.
+pub const TIMELINE_RATE: f64 = 60.0;
+/// Samples of input history kept for each player, the length of each player's timeline in the
+/// shaders' `input_history` buffer.
+pub const TIMELINE_LENGTH: usize = 600;
+/// Longest history a timeline can show.
+pub const MAX_TIMELINE_DURATION: f64 = TIMELINE_LENGTH as f64 / TIMELINE_RATE;
+
+/// Bits of a packed sample holding each trigger's analog value.
+const LEFT_TRIGGER_SHIFT: u32 = 16;
+const RIGHT_TRIGGER_SHIFT: u32 = 24;
+
+/// Ring buffer of the recent inputs of every controller shown, newest first, sampled at
+/// [`TIMELINE_RATE`].
+///
+/// Each sample is packed into a `u32`, the lower bits holding the buttons held at any point during
+/// the sample as returned by [`held_buttons`], followed by the highest value of the left and right
+/// triggers in the upper two bytes.
+#[derive(Debug)]
+pub struct InputHistory {
+    samples: VecDeque<[u32; MAX_PLAYERS]>,
+    next_sample: Option<Instant>,
+}
+
+impl InputHistory {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(TIMELINE_LENGTH),
+            next_sample: None,
+        }
+    }
+
+    /// Records each player's input at `now`, starting new samples for the time passed since the
+    /// last update.
+    pub fn update<'i>(&mut self, now: Instant, inputs: impl IntoIterator<Item = &'i Input>) {
+        let period = Duration::from_secs_f64(1.0 / TIMELINE_RATE);
+        let mut next_sample = *self.next_sample.get_or_insert(now);
+
+        // Start over rather than catching up sample by sample after a long pause.
+        if now.saturating_duration_since(next_sample) > period * TIMELINE_LENGTH as u32 {
+            next_sample = now;
+        }
+
+        let packed = inputs.into_iter().map(pack).collect::<Vec<_>>();
+        while next_sample <= now {
+            if self.samples.len() == TIMELINE_LENGTH {
+                self.samples.pop_back();
+            }
+            self.samples.push_front([0; MAX_PLAYERS]);
+            next_sample += period;
+        }
+        self.next_sample = Some(next_sample);
+
+        // Merge into the current sample so presses shorter than a sample still show up.
+        let current = self.samples.front_mut().unwrap();
+        for (sample, packed) in current.iter_mut().zip(packed) {
+            let trigger = |sample: u32, shift| (sample >> shift) & 0xFF;
+            let left =
+                trigger(*sample, LEFT_TRIGGER_SHIFT).max(trigger(packed, LEFT_TRIGGER_SHIFT));
+            let right =
+                trigger(*sample, RIGHT_TRIGGER_SHIFT).max(trigger(packed, RIGHT_TRIGGER_SHIFT));
+            *sample = (*sample | packed) & 0xFFFF
+                | left << LEFT_TRIGGER_SHIFT
+                | right << RIGHT_TRIGGER_SHIFT;
+        }
+    }
+
+    /// Flattens the history into the layout of the `input_history` buffer, [`TIMELINE_LENGTH`]
+    /// samples for each player. Samples from before the history starts have nothing pressed.
+    pub fn to_raw(&self) -> Vec<u32> {
+        (0..MAX_PLAYERS)
+            .flat_map(|player| {
+                (0..TIMELINE_LENGTH)
+                    .map(move |i| self.samples.get(i).map_or(0, |sample| sample[player]))
+            })
+            .collect()
+    }
+}
+
+impl Default for InputHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn pack(input: &Input) -> u32 {
+    held_buttons(input)
+        | u32::from(input.left_trigger) << LEFT_TRIGGER_SHIFT
+        | u32::from(input.right_trigger) << RIGHT_TRIGGER_SHIFT
+}
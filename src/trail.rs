@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+
+use crate::{control::Stick, state::MAX_PLAYERS};
+
+/// Most frames of stick history kept, the length of each stick's trail in the shaders'
+/// `stick_history` buffer.
+pub const MAX_TRAIL_LENGTH: usize = 64;
+
+const STICKS: usize = 2;
+
+/// Trail settings as laid out in the shaders' `trail` uniform.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TrailUniform {
+    /// Frames of history drawn behind each stick, 0 when trails are disabled.
+    pub length: u32,
+    /// Opacity kept by each frame older point of the trail.
+    pub fade: f32,
+}
+
+/// Ring buffer of the last positions of every stick shown, newest first.
+#[derive(Debug)]
+pub struct StickHistory {
+    length: usize,
+    positions: Vec<VecDeque<[f32; 2]>>,
+}
+
+impl StickHistory {
+    pub fn new(length: usize) -> Self {
+        let length = length.min(MAX_TRAIL_LENGTH);
+        Self {
+            length,
+            positions: vec![VecDeque::with_capacity(length); MAX_PLAYERS * STICKS],
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Records the position of `player`'s `stick` for the current frame.
+    pub fn push(&mut self, player: usize, stick: Stick, position: cgmath::Vector2<f32>) {
+        let Some(positions) = self.positions.get_mut(Self::index(player, stick)) else {
+            return;
+        };
+
+        if positions.len() == self.length {
+            positions.pop_back();
+        }
+        if self.length > 0 {
+            positions.push_front(position.into());
+        }
+    }
+
+    /// Flattens the history into the layout of the `stick_history` buffer, [`MAX_TRAIL_LENGTH`]
+    /// positions for each player's main stick followed by their C stick. Sticks with a shorter
+    /// history repeat their oldest position.
+    pub fn to_raw(&self) -> Vec<[f32; 2]> {
+        self.positions
+            .iter()
+            .flat_map(|positions| {
+                let oldest = positions.back().copied().unwrap_or_default();
+                positions
+                    .iter()
+                    .copied()
+                    .chain(std::iter::repeat(oldest))
+                    .take(MAX_TRAIL_LENGTH)
+            })
+            .collect()
+    }
+
+    fn index(player: usize, stick: Stick) -> usize {
+        let stick = match stick {
+            Stick::Main => 0,
+            Stick::C => 1,
+        };
+        player * STICKS + stick
+    }
+}
@@ -0,0 +1,392 @@
+//! Client for gcfeeder's UDP input server.
+//!
+//! Servers supporting the push protocol are sent a [`SUBSCRIBE`] datagram, which they answer with
+//! a [`FRAME_MAGIC`] frame holding the latest input. They then send a frame with the next sequence
+//! number whenever the input changes, for as long as the subscription is renewed at least every
+//! few seconds. Frames are the magic, a little-endian `u32` sequence number and the bincode
+//! encoded input.
+//!
+//! Older servers reply to any datagram with just the encoded input, in which case the server is
+//! polled with empty datagrams instead.
+
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{atomic::Ordering, Arc},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use enclose::enclose;
+use gcinput::Input;
+
+use crate::{
+    source::{InputSource, Sample, Shared, Status},
+    stats::PacketStats,
+};
+
+/// Subscribes to the push protocol, followed by the protocol version.
+pub const SUBSCRIBE: [u8; 5] = [b'G', b'C', b'V', b'S', 1];
+/// Starts every frame sent by a server using the push protocol.
+pub const FRAME_MAGIC: [u8; 4] = *b"GCVF";
+const FRAME_HEADER_SIZE: usize = FRAME_MAGIC.len() + 4;
+
+const SOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How often the subscription is renewed. Servers also answer renewals with the latest frame, so
+/// this doubles as a heartbeat while the input isn't changing.
+const SUBSCRIBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a request may go unanswered before the connection is considered stale.
+const STALE_TIMEOUT: Duration = Duration::from_millis(250);
+/// How long a request may go unanswered before the server is considered gone and polling backs
+/// off.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Largest difference between the sequence numbers of consecutive frames that is put down to lost
+/// or reordered frames. Larger jumps are taken as the server having restarted its sequence.
+const REORDER_WINDOW: u32 = 256;
+
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Reads inputs from gcfeeder's UDP input server.
+///
+/// The server doesn't need to be running yet. Connecting backs off while it doesn't reply and
+/// resumes when it comes back.
+pub struct UdpSource {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl UdpSource {
+    /// Connects to the server at `host`, an IP address or hostname, on `port`.
+    pub fn spawn(host: String, port: u16) -> Self {
+        let shared = Arc::new(Shared::default());
+        let thread = thread::spawn(enclose!((shared) move || {
+            Connection::new(host, port, &shared).run();
+        }));
+
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    /// Waiting for the reply to the first subscription to tell which protocol the server speaks.
+    Unknown,
+    Push,
+    /// The server predates the push protocol and is polled with empty datagrams.
+    Poll,
+}
+
+struct Connection<'a> {
+    host: String,
+    port: u16,
+    shared: &'a Shared,
+    socket: Option<UdpSocket>,
+    open_failed: bool,
+    protocol: Protocol,
+    input_size: usize,
+    buf: Vec<u8>,
+    previous: Vec<u8>,
+    sequence: Option<u32>,
+    last_subscribe: Option<Instant>,
+    /// When the oldest request still waiting for a reply was sent. Pushed frames arrive only when
+    /// the input changes, so the connection is judged by whether requests are answered instead.
+    unanswered_since: Option<Instant>,
+    replied: bool,
+    retry_delay: Duration,
+}
+
+impl<'a> Connection<'a> {
+    fn new(host: String, port: u16, shared: &'a Shared) -> Self {
+        let input_size = bincode::serialized_size(&Input::default()).unwrap() as usize;
+
+        Self {
+            host,
+            port,
+            shared,
+            socket: None,
+            open_failed: false,
+            protocol: Protocol::Unknown,
+            input_size,
+            buf: vec![0u8; FRAME_HEADER_SIZE + input_size],
+            previous: Vec::new(),
+            sequence: None,
+            last_subscribe: None,
+            unanswered_since: None,
+            replied: false,
+            retry_delay: MIN_RETRY_DELAY,
+        }
+    }
+
+    fn run(mut self) {
+        log::info!(
+            "Waiting for input server on {}",
+            display_addr(&self.host, self.port)
+        );
+
+        while !self.shared.stop_flag.load(Ordering::Acquire) {
+            if self.socket.is_none() {
+                self.open();
+            }
+
+            if self.socket.is_some() {
+                self.send();
+                self.receive();
+            }
+
+            let status = self.status();
+            if self.shared.set_status(status) != status {
+                match status {
+                    Status::Connected => log::info!("Connected to input server"),
+                    Status::Stale => log::warn!("Input server stopped responding"),
+                    Status::Disconnected => {
+                        log::warn!("Lost connection to input server, retrying")
+                    }
+                }
+            }
+
+            if status == Status::Disconnected {
+                // Reopen the socket on the next attempt to pick up changes to the host's address
+                // and to the protocol the server speaks.
+                self.socket = None;
+                sleep_unless_stopped(self.shared, self.retry_delay);
+                self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY_DELAY);
+            } else {
+                self.retry_delay = MIN_RETRY_DELAY;
+            }
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self.unanswered_since.map(|t| t.elapsed()) {
+            _ if !self.replied => Status::Disconnected,
+            Some(elapsed) if elapsed >= DISCONNECT_TIMEOUT => Status::Disconnected,
+            Some(elapsed) if elapsed >= STALE_TIMEOUT => Status::Stale,
+            _ => Status::Connected,
+        }
+    }
+
+    fn open(&mut self) {
+        match open_socket(&self.host, self.port) {
+            Ok(socket) => {
+                self.socket = Some(socket);
+                self.open_failed = false;
+                self.protocol = Protocol::Unknown;
+                self.sequence = None;
+                self.last_subscribe = None;
+            }
+            // Only report the first failure, e.g. the host not resolving, until it works.
+            Err(e) if !self.open_failed => {
+                log::error!("Failed to open socket: {e}");
+                self.open_failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn send(&mut self) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        match self.protocol {
+            Protocol::Poll => {
+                let _ = socket.send(&[]);
+            }
+            Protocol::Unknown | Protocol::Push => {
+                if self
+                    .last_subscribe
+                    .is_some_and(|t| t.elapsed() < SUBSCRIBE_INTERVAL)
+                {
+                    return;
+                }
+                let _ = socket.send(&SUBSCRIBE);
+                self.last_subscribe = Some(Instant::now());
+            }
+        }
+        self.unanswered_since.get_or_insert_with(Instant::now);
+    }
+
+    fn receive(&mut self) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        // Errors are expected while the server isn't running, e.g. connection refused.
+        let received = match socket.recv(&mut self.buf) {
+            Ok(received) => received,
+            Err(e) => {
+                let timed_out = matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                );
+                // Only count unanswered polls as lost while the server is around.
+                if timed_out
+                    && self.protocol == Protocol::Poll
+                    && self.status() != Status::Disconnected
+                {
+                    self.shared.packet_stats.lock().unwrap().record_lost(1);
+                }
+                return;
+            }
+        };
+
+        let (input, sequence) = if received == self.input_size {
+            if self.protocol == Protocol::Unknown {
+                log::info!("Input server doesn't support the push protocol, polling instead");
+                self.protocol = Protocol::Poll;
+            }
+            (0..received, None)
+        } else if received == self.buf.len() && self.buf.starts_with(&FRAME_MAGIC) {
+            if self.protocol == Protocol::Unknown {
+                self.protocol = Protocol::Push;
+            }
+
+            let sequence = &self.buf[FRAME_MAGIC.len()..FRAME_HEADER_SIZE];
+            (
+                FRAME_HEADER_SIZE..received,
+                Some(u32::from_le_bytes(sequence.try_into().unwrap())),
+            )
+        } else {
+            log::warn!("Socket received incomplete data of size {}", received);
+            return;
+        };
+
+        // Answers to renewals repeat the latest frame, which would skew the statistics of frames
+        // pushed as the input changes.
+        let repeated = sequence.is_some() && sequence == self.sequence;
+        if sequence.is_some_and(|sequence| !self.accept_sequence(sequence)) {
+            return;
+        }
+        if !repeated {
+            self.shared
+                .packet_stats
+                .lock()
+                .unwrap()
+                .record_received(Instant::now());
+        }
+        // Only frames that are shown keep the connection alive, so a server stuck sending
+        // discarded frames doesn't look connected.
+        self.unanswered_since = None;
+        self.replied = true;
+
+        let data = &self.buf[input];
+        // The server repeats the latest input when polled or resubscribed, so skip repeats.
+        if data != self.previous {
+            match bincode::deserialize(data) {
+                Ok(input) => self.shared.push(input),
+                Err(e) => log::error!("Failed to decode input: {e}"),
+            }
+            self.previous.clear();
+            self.previous.extend_from_slice(data);
+        }
+    }
+
+    /// Returns whether a frame with `sequence` is newer than every frame received so far, logging
+    /// frames that were skipped.
+    fn accept_sequence(&mut self, sequence: u32) -> bool {
+        let Some(previous) = self.sequence else {
+            self.sequence = Some(sequence);
+            return true;
+        };
+
+        // Sequence numbers wrap around, so compare them by their distance.
+        match sequence.wrapping_sub(previous) {
+            0 => true,
+            delta if delta <= REORDER_WINDOW => {
+                if delta > 1 {
+                    log::debug!("Dropped {} input frames", delta - 1);
+                    self.shared
+                        .packet_stats
+                        .lock()
+                        .unwrap()
+                        .record_lost((delta - 1).into());
+                }
+                self.sequence = Some(sequence);
+                true
+            }
+            delta if delta.wrapping_neg() <= REORDER_WINDOW => {
+                log::debug!("Discarded out of order input frame {sequence}");
+                self.shared
+                    .packet_stats
+                    .lock()
+                    .unwrap()
+                    .record_out_of_order();
+                false
+            }
+            // A server restarted faster than the disconnect timeout starts over from its first
+            // sequence number.
+            _ => {
+                log::info!("Input server restarted its frame sequence at {sequence}");
+                self.sequence = Some(sequence);
+                true
+            }
+        }
+    }
+}
+
+/// Resolves `host` every time the socket is opened so that hostnames pointing to a new address are
+/// followed after a reconnect.
+fn open_socket(host: &str, port: u16) -> io::Result<UdpSocket> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{host} has no address")))?;
+    let socket = match addr {
+        SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+        SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+    };
+    socket.connect(addr)?;
+    socket.set_read_timeout(Some(SOCK_TIMEOUT))?;
+    socket.set_write_timeout(Some(SOCK_TIMEOUT))?;
+    Ok(socket)
+}
+
+fn display_addr(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// Sleeps in short steps so that dropping the source isn't held up by a long retry delay.
+fn sleep_unless_stopped(shared: &Shared, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !shared.stop_flag.load(Ordering::Acquire) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        thread::sleep(remaining.min(SOCK_TIMEOUT));
+    }
+}
+
+impl InputSource for UdpSource {
+    fn poll(&mut self, _elapsed: Duration, samples: &mut Vec<Sample>) {
+        self.shared.drain_into(samples);
+    }
+
+    fn status(&self) -> Status {
+        self.shared.status()
+    }
+
+    fn packet_stats(&self) -> Option<PacketStats> {
+        Some(self.shared.packet_stats.lock().unwrap().clone())
+    }
+}
+
+impl Drop for UdpSource {
+    fn drop(&mut self) {
+        self.shared.stop_flag.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls a file's modification time and yields its new contents when it changes.
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let modified = modified_time(&path).ok();
+
+        Self {
+            path,
+            modified,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn poll(&mut self) -> Option<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = modified_time(&self.path).ok()?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+
+        // Editors often truncate before writing, so wait for a non-empty read before
+        // considering the change handled.
+        let contents = fs::read_to_string(&self.path).ok()?;
+        if contents.is_empty() {
+            return None;
+        }
+
+        self.modified = Some(modified);
+        Some(contents)
+    }
+}
+
+fn modified_time(path: &Path) -> io::Result<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified())
+}
@@ -16,6 +16,94 @@ var<uniform> camera: CameraUniform;
 var<uniform> resolution: vec2<f32>;
 @group(1) @binding(2)
 var<uniform> time: f32;
+// Connection status of each player's input source.
+@group(1) @binding(3)
+var<uniform> connection: vec4<u32>;
+// Number of controllers shown, up to 4.
+@group(1) @binding(4)
+var<uniform> players: u32;
+
+struct Trail {
+    // Frames of history drawn behind each stick, 0 when trails are disabled.
+    length: u32,
+    // Opacity kept by each frame older point of the trail.
+    fade: f32,
+}
+// Positions of every stick over the last frames, newest first and in the same space as
+// `stick_position`. Each player has MAX_TRAIL_LENGTH positions for their main stick followed by
+// as many for their C stick.
+@group(1) @binding(5)
+var<storage, read> stick_history: array<vec2<f32>>;
+@group(1) @binding(6)
+var<uniform> trail: Trail;
+
+const MAX_TRAIL_LENGTH = 64u;
+
+// Inputs of every player over the last seconds, newest first and sampled TIMELINE_RATE times a
+// second. Each player has TIMELINE_LENGTH samples. The lower bits of a sample hold the buttons
+// held in the order of the timeline rows, and the upper two bytes the left and right triggers.
+@group(1) @binding(7)
+var<storage, read> input_history: array<u32>;
+// Seconds of input history shown by timelines.
+@group(1) @binding(8)
+var<uniform> timeline_duration: f32;
+
+const TIMELINE_RATE = 60.0;
+const TIMELINE_LENGTH = 600u;
+// Rows of a timeline from top to bottom: A, B, X, Y, Z, L, R, Start, Up, Down, Left, Right, then
+// the left and right trigger values.
+const TIMELINE_BUTTONS = 12u;
+const TIMELINE_ROWS = 14u;
+
+struct GameFrames {
+    // Game frames since counting started.
+    frame: u32,
+    // Whether inputs are quantised into game frames, set by --game-frames.
+    enabled: u32,
+    // Each player's buttons that were last held for a single game frame, one bit each in the
+    // order of the timeline rows.
+    highlights: vec4<u32>,
+}
+@group(1) @binding(9)
+var<uniform> game_frames: GameFrames;
+
+const FRAME_COUNTER_DIGITS = 6u;
+const HIGHLIGHT_COLOR = vec3<f32>(1.0, 0.55, 0.0);
+
+// Gate, deadzone and thresholds drawn for a stick. Distances are in the stick's local space, where
+// the center of the stick is at `-stick_position`.
+struct Gate {
+    // GATE_OCTAGON, GATE_CIRCLE or GATE_NOTCHES.
+    shape: u32,
+    notch_count: u32,
+    // Radius of a GATE_CIRCLE gate.
+    radius: f32,
+    // Radius of the deadzone, 0 to draw none.
+    deadzone: f32,
+    // Length of one unit of Melee's stick coordinates, 0 to not mark Melee's thresholds.
+    melee_unit: f32,
+    // Positions of the notches of a GATE_NOTCHES gate in order around it, in `xy`.
+    notches: array<vec4<f32>, MAX_NOTCHES>,
+}
+// Gates of the first MAX_GATES sticks of the layout, indexed by `gate`.
+@group(1) @binding(10)
+var<uniform> gates: array<Gate, MAX_GATES>;
+
+const MAX_GATES = 8u;
+const MAX_NOTCHES = 16u;
+const GATE_OCTAGON = 0u;
+const GATE_CIRCLE = 1u;
+const GATE_NOTCHES = 2u;
+const MARKER_COLOR = vec3<f32>(0.5, 0.5, 0.5);
+// Thresholds in Melee's stick coordinates.
+const MELEE_DEADZONE = 0.2875;
+const MELEE_DASH = 0.8;
+const MELEE_TAP_JUMP = 0.6625;
+const MELEE_FAST_FALL = -0.6625;
+
+const CONNECTED = 0u;
+const DISCONNECTED = 1u;
+const STALE = 2u;
 
 const BUTTON_A = 0u;
 const BUTTON_B = 1u;
@@ -32,6 +120,8 @@ const BUTTON_LEFT = 11u;
 const BUTTON_RIGHT = 12u;
 const BUTTON_DOWN = 13u;
 const BACKGROUND = 14u;
+const TIMELINE = 15u;
+const FRAME_COUNTER = 16u;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -49,6 +139,8 @@ struct InstanceInput {
     @location(12) button_pressed: u32,
     @location(13) trigger_fill: f32,
     @location(14) stick_position: vec2<f32>,
+    @location(15) player: u32,
+    @location(4) gate: u32,
 }
 
 struct VertexOutput {
@@ -61,6 +153,58 @@ struct VertexOutput {
     @location(5) button_pressed: u32,
     @location(6) trigger_fill: f32,
     @location(7) stick_position: vec2<f32>,
+    @location(8) player: u32,
+    @location(9) gate: u32,
+}
+
+// Port colors, used to tell controllers apart when showing several.
+fn player_color(player: u32) -> vec3<f32> {
+    switch player {
+        case 0u {
+            return vec3<f32>(0.9, 0.2, 0.2);
+        }
+        case 1u {
+            return vec3<f32>(0.2, 0.4, 0.9);
+        }
+        case 2u {
+            return vec3<f32>(0.9, 0.8, 0.2);
+        }
+        default {
+            return vec3<f32>(0.2, 0.8, 0.3);
+        }
+    }
+}
+
+// Greys out the controller while the input source is disconnected, and partially while stale.
+// Draws `top` over `bottom`, both with straight alpha.
+fn composite_over(top: vec4<f32>, bottom: vec4<f32>) -> vec4<f32> {
+    let alpha = top.a + bottom.a * (1.0 - top.a);
+    if alpha <= 0.0 {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>((top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / alpha, alpha);
+}
+
+fn connection_color(color: vec4<f32>, player: u32) -> vec4<f32> {
+    let grey = vec3<f32>(dot(color.rgb, vec3<f32>(0.299, 0.587, 0.114)) * 0.5);
+    switch connection[player] {
+        case DISCONNECTED {
+            return vec4<f32>(grey, color.a);
+        }
+        case STALE {
+            return vec4<f32>(mix(color.rgb, grey, 0.5), color.a);
+        }
+        default {
+            return color;
+        }
+    }
+}
+
+fn background_color(player: u32) -> vec3<f32> {
+    if players > 1u {
+        return player_color(player) * 0.15;
+    }
+    return vec3<f32>(0.0, 0.0, 0.0);
 }
 
 fn border_width(in: VertexOutput) -> f32 {
@@ -102,7 +246,109 @@ fn clip_sdf_button(in: VertexOutput) {
     }
 }
 
-fn clip_stick(in: VertexOutput) {
+// Position of the stick `age` frames ago.
+fn stick_history_position(in: VertexOutput, age: u32) -> vec2<f32> {
+    let stick = in.which - STICK_MAIN;
+    return stick_history[(in.player * 2u + stick) * MAX_TRAIL_LENGTH + age];
+}
+
+fn segment_distance(p: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let ab = b - a;
+    let t = clamp(dot(p - a, ab) / max(dot(ab, ab), 1e-6), 0.0, 1.0);
+    return length(p - (a + ab * t));
+}
+
+// Opacity of the trail left by the stick's past positions, 0 away from the trail.
+fn stick_trail(in: VertexOutput, width: f32) -> f32 {
+    var opacity = 0.0;
+    var fade = 1.0;
+    for (var age = 1u; age < trail.length; age++) {
+        // Stick positions are flipped relative to the fragment position.
+        let a = -stick_history_position(in, age - 1u);
+        let b = -stick_history_position(in, age);
+        let dist = segment_distance(in.position, a, b);
+        opacity = max(opacity, fade * (1.0 - smoothstep(0.5 * width, width, dist)));
+        fade *= trail.fade;
+    }
+    return opacity;
+}
+
+// Adds a marker `dist` away from the fragment, highlighted once the stick has crossed it.
+fn add_marker(markers: vec4<f32>, dist: f32, width: f32, crossed: bool) -> vec4<f32> {
+    if dist >= width || markers.a >= 1.0 {
+        return markers;
+    }
+    if crossed {
+        return vec4<f32>(HIGHLIGHT_COLOR, 1.0);
+    }
+    return vec4<f32>(MARKER_COLOR, max(markers.a, 0.6));
+}
+
+// Color and opacity of the stick's gate, deadzone and threshold markers, and a dot at the stick's
+// center whenever any are drawn. Transparent away from them.
+fn stick_markers(in: VertexOutput) -> vec4<f32> {
+    if in.gate >= MAX_GATES {
+        return vec4<f32>(0.0);
+    }
+    let gate = gates[in.gate];
+    let p = in.position;
+    let stick = -in.stick_position;
+    let width = 0.06 * border_width(in);
+    var markers = vec4<f32>(0.0);
+
+    if gate.shape == GATE_CIRCLE {
+        markers = add_marker(markers, abs(length(p) - gate.radius), width, false);
+    } else if gate.shape == GATE_NOTCHES && gate.notch_count > 1u {
+        var dist = 1e6;
+        for (var i = 0u; i < min(gate.notch_count, MAX_NOTCHES); i++) {
+            let a = gate.notches[i].xy;
+            let b = gate.notches[(i + 1u) % min(gate.notch_count, MAX_NOTCHES)].xy;
+            dist = min(dist, segment_distance(p, a, b));
+        }
+        markers = add_marker(markers, dist, width, false);
+    }
+
+    if gate.deadzone > 0.0 {
+        let crossed = length(stick) > gate.deadzone;
+        markers = add_marker(markers, abs(length(p) - gate.deadzone), width, crossed);
+    }
+
+    let unit = gate.melee_unit;
+    if unit > 0.0 {
+        // Melee clamps coordinates to the unit circle before comparing them to thresholds.
+        var m = stick / unit;
+        if length(m) > 1.0 {
+            m = normalize(m);
+        }
+        let q = p / unit;
+        if length(q) <= 1.0 {
+            for (var side = -1.0; side <= 1.0; side += 2.0) {
+                markers = add_marker(markers, abs(q.x - side * MELEE_DEADZONE) * unit, width,
+                    m.x * side >= MELEE_DEADZONE);
+                markers = add_marker(markers, abs(q.y - side * MELEE_DEADZONE) * unit, width,
+                    m.y * side >= MELEE_DEADZONE);
+                markers = add_marker(markers, abs(q.x - side * MELEE_DASH) * unit, width,
+                    m.x * side >= MELEE_DASH);
+            }
+            markers = add_marker(markers, abs(q.y - MELEE_TAP_JUMP) * unit, width,
+                m.y >= MELEE_TAP_JUMP);
+            markers = add_marker(markers, abs(q.y - MELEE_FAST_FALL) * unit, width,
+                m.y <= MELEE_FAST_FALL);
+        }
+        markers = add_marker(markers, abs(length(q) - 1.0) * unit, width,
+            length(stick) >= unit);
+    }
+
+    let marked = gate.shape != GATE_OCTAGON || gate.deadzone > 0.0 || unit > 0.0;
+    if marked && length(p - stick) < 2.0 * width {
+        return vec4<f32>(1.0);
+    }
+    return markers;
+}
+
+// Returns the opacity of the stick's color, 1 for the stick itself, less for its trail and 0 for
+// fragments covered only by `marked` markers.
+fn clip_stick(in: VertexOutput, marked: bool) -> f32 {
     let bw = border_width(in);
     let is_c_stick = in.which == 7u;
     var radius = 0.225;
@@ -115,11 +361,175 @@ fn clip_stick(in: VertexOutput) {
 
     let scaled_uv = (in.tex_coords - 0.5) / 0.85 + 0.5;
     let sdf_dist = textureSample(octagon_t_diffuse, s_diffuse, scaled_uv).r;
+    // Other gate shapes are drawn as markers instead.
+    let octagon = in.gate >= MAX_GATES || gates[in.gate].shape == GATE_OCTAGON;
 
-    if (dist < 0.0 && (sdf_dist < 0.5 - bw || sdf_dist > 0.5))
+    if (dist < 0.0 && (!octagon || sdf_dist < 0.5 - bw || sdf_dist > 0.5))
         || (!is_c_stick && dist > radius * bw) {
+        let trail_opacity = stick_trail(in, 0.4 * radius * bw);
+        if trail_opacity <= 0.0 && !marked {
+            discard;
+        }
+        return trail_opacity;
+    }
+    return 1.0;
+}
+
+// Packed input of the player `age` seconds ago.
+fn input_history_sample(player: u32, age: f32) -> u32 {
+    let index = min(u32(max(age, 0.0) * TIMELINE_RATE), TIMELINE_LENGTH - 1u);
+    return input_history[player * TIMELINE_LENGTH + index];
+}
+
+fn timeline_row(in: VertexOutput) -> u32 {
+    return min(u32((0.5 - in.position.y) * f32(TIMELINE_ROWS)), TIMELINE_ROWS - 1u);
+}
+
+// Returns 1 where the row's button is held or below the row's trigger value, a faint fill for the
+// rest of the row and 0 between rows. The newest input is at the right edge.
+fn timeline_fill(in: VertexOutput) -> f32 {
+    let row = timeline_row(in);
+    let within_row = fract((0.5 - in.position.y) * f32(TIMELINE_ROWS));
+    let gap = 0.15;
+    if within_row < gap || within_row > 1.0 - gap {
+        return 0.0;
+    }
+
+    let sample = input_history_sample(in.player, (0.5 - in.position.x) * timeline_duration);
+    var filled: bool;
+    if row < TIMELINE_BUTTONS {
+        filled = ((sample >> row) & 1u) == 1u;
+    } else {
+        let shift = 16u + 8u * (row - TIMELINE_BUTTONS);
+        let value = f32((sample >> shift) & 0xFFu) / 255.0;
+        filled = (1.0 - gap - within_row) / (1.0 - 2.0 * gap) < value;
+    }
+
+    if filled {
+        return 1.0;
+    }
+    return 0.15;
+}
+
+// Bit of the button or trigger in `game_frames.highlights`, 32 for other controls.
+fn highlight_bit(which: u32) -> u32 {
+    switch which {
+        case 0u, 1u, 2u, 3u { // A, B, X, Y
+            return which;
+        }
+        case 5u { // Z
+            return 4u;
+        }
+        case 8u, 9u { // Left Trigger, Right Trigger
+            return which - 3u;
+        }
+        case 4u { // Start
+            return 7u;
+        }
+        case 10u, 11u, 12u, 13u { // Dpad
+            return which - 2u;
+        }
+        default {
+            return 32u;
+        }
+    }
+}
+
+// Whether the control was last held for just a single game frame.
+fn is_highlighted(in: VertexOutput) -> bool {
+    let bit = highlight_bit(in.which);
+    return bit < 32u && ((game_frames.highlights[in.player] >> bit) & 1u) == 1u;
+}
+
+// Segments of a seven-segment digit, one bit each from top clockwise, then the middle.
+fn digit_segments(digit: u32) -> u32 {
+    switch digit {
+        case 0u {
+            return 0x3Fu;
+        }
+        case 1u {
+            return 0x06u;
+        }
+        case 2u {
+            return 0x5Bu;
+        }
+        case 3u {
+            return 0x4Fu;
+        }
+        case 4u {
+            return 0x66u;
+        }
+        case 5u {
+            return 0x6Du;
+        }
+        case 6u {
+            return 0x7Du;
+        }
+        case 7u {
+            return 0x07u;
+        }
+        case 8u {
+            return 0x7Fu;
+        }
+        default {
+            return 0x6Fu;
+        }
+    }
+}
+
+// Returns the segment of a seven-segment digit at `p`, from (0, 0) at the bottom left of the digit
+// to (1, 1) at the top right, or 7 between segments.
+fn digit_segment_at(p: vec2<f32>) -> u32 {
+    let thickness = vec2<f32>(0.2, 0.1);
+    let left = p.x < thickness.x;
+    let right = p.x > 1.0 - thickness.x;
+    let upper = p.y > 0.5;
+    if p.y > 1.0 - thickness.y {
+        return 0u;
+    } else if right && upper {
+        return 1u;
+    } else if right {
+        return 2u;
+    } else if p.y < thickness.y {
+        return 3u;
+    } else if left && !upper {
+        return 4u;
+    } else if left {
+        return 5u;
+    } else if abs(p.y - 0.5) < thickness.y * 0.5 {
+        return 6u;
+    }
+    return 7u;
+}
+
+// Draws the game frame counter as seven-segment digits, returning 1 for lit segments and a faint
+// fill for unlit ones. Nothing is drawn unless inputs are quantised into game frames.
+fn clip_frame_counter(in: VertexOutput) -> f32 {
+    if game_frames.enabled == 0u {
+        discard;
+    }
+
+    let x = (in.position.x + 0.5) * f32(FRAME_COUNTER_DIGITS);
+    let index = min(u32(x), FRAME_COUNTER_DIGITS - 1u);
+    let margin = vec2<f32>(0.15, 0.1);
+    let p = (vec2<f32>(fract(x), in.position.y + 0.5) - margin) / (1.0 - 2.0 * margin);
+    if any(p < vec2<f32>(0.0)) || any(p > vec2<f32>(1.0)) {
+        discard;
+    }
+    let segment = digit_segment_at(p);
+    if segment == 7u {
         discard;
     }
+
+    var place = 1u;
+    for (var i = index + 1u; i < FRAME_COUNTER_DIGITS; i++) {
+        place *= 10u;
+    }
+    let digit = (game_frames.frame / place) % 10u;
+    if ((digit_segments(digit) >> segment) & 1u) == 1u {
+        return 1.0;
+    }
+    return 0.1;
 }
 
 fn clip_trigger(in: VertexOutput) {
@@ -181,11 +591,16 @@ fn vs_main(
     out.button_pressed = instance.button_pressed;
     out.trigger_fill = instance.trigger_fill;
     out.stick_position = instance.stick_position;
+    out.player = instance.player;
+    out.gate = instance.gate;
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var stick_opacity = 1.0;
+    var markers = vec4<f32>(0.0);
+    var counter_fill = 0.0;
     switch in.which {
         case 0u, 1u, 4u, 10u, 11u, 12u, 13u { // A, B, Start, Dpad
             clip_circle_button(in);
@@ -194,18 +609,22 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
             clip_sdf_button(in);
         }
         case 6u, 7u { // Main Stick, C Stick
-            clip_stick(in);
+            markers = stick_markers(in);
+            stick_opacity = clip_stick(in, markers.a > 0.0);
         }
         case 8u, 9u { // Left Trigger, Right Trigger
             clip_trigger(in);
         }
+        case 16u { // Frame Counter
+            counter_fill = clip_frame_counter(in);
+        }
         default {}
     }
 
     var color: vec4<f32>;
     switch in.which {
         case 14u { // Background
-            color = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+            color = vec4<f32>(background_color(in.player), 1.0);
         }
         default {
             let screen_pos = (in.clip_position.xy / resolution) * vec2<f32>(1.0, -1.0)
@@ -214,5 +633,19 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
         }
     }
 
-    return color;
+    if is_highlighted(in) {
+        color = vec4<f32>(HIGHLIGHT_COLOR, color.a);
+    }
+    // Faded parts are made translucent rather than mixed with the background color, so that a
+    // transparent background shows through them.
+    if in.which == FRAME_COUNTER {
+        color.a *= counter_fill;
+    }
+    if in.which == TIMELINE {
+        color.a *= timeline_fill(in);
+    }
+    color.a *= stick_opacity;
+    color = composite_over(markers, color);
+
+    return connection_color(color, in.player);
 }